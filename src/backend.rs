@@ -0,0 +1,392 @@
+use crate::config::{Config, WallpaperCmd};
+use crate::ipc;
+use crate::state::WallpaperEntry;
+use crate::template::{self, Values};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A program that draws wallpapers, selected with the `backend` config value.
+pub trait Backend {
+    /// Fit modes (see `config::WALLPAPER_MODES`) the backend can apply.
+    fn modes(&self) -> &'static [&'static str];
+
+    /// Loads an image before it is applied, for backends that keep images in memory.
+    fn preload(&self, _path: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Frees the images that are no longer shown on any monitor.
+    fn unload_unused(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Succeeds once the backend can take requests, e.g. its daemon is running.
+    fn ready(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()>;
+
+    /// Path of the image currently shown on `monitor`.
+    fn current(&self, monitor: &str) -> Result<String>;
+}
+
+/// The backend configured for a monitor (pass the config from `Config::for_monitor`).
+pub fn from_config(config: &Config) -> Box<dyn Backend> {
+    match config.backend.as_str() {
+        "swww" => Box::new(Swww),
+        "swaybg" => Box::new(Swaybg),
+        "wpaperd" => Box::new(Wpaperd),
+        "mpvpaper" => Box::new(Mpvpaper),
+        "command" => Box::new(Shell {
+            template: config.wallpaper_cmd.clone(),
+        }),
+        _ => Box::new(Hyprpaper),
+    }
+}
+
+/// Runs a command to completion, failing with its output if it doesn't succeed.
+fn run(command: &mut Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to execute {}", program))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            if stderr.is_empty() { stdout } else { stderr }
+        );
+    }
+    Ok(stdout)
+}
+
+fn unsupported_mode(backend: &str, mode: &str) -> anyhow::Error {
+    anyhow::anyhow!("{} does not support wallpaper_mode \"{}\"", backend, mode)
+}
+
+/// hyprpaper, driven over its IPC socket.
+struct Hyprpaper;
+
+impl Hyprpaper {
+    /// Sends a request that hyprpaper answers with "ok".
+    fn request(&self, request: &str) -> Result<()> {
+        let reply = self.query(request)?;
+        if reply.trim() != "ok" {
+            anyhow::bail!("hyprpaper rejected {:?}: {}", request, reply.trim());
+        }
+        Ok(())
+    }
+
+    fn query(&self, request: &str) -> Result<String> {
+        ipc::request(&ipc::hyprpaper_socket()?, request).context("Failed to talk to hyprpaper")
+    }
+
+    /// Images hyprpaper holds in memory.
+    fn loaded(&self) -> Result<Vec<String>> {
+        // One path per line, or a message when nothing is loaded
+        Ok(self
+            .query("listloaded")?
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with('/'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Monitor and image pairs for every monitor showing one.
+    fn active(&self) -> Result<Vec<(String, String)>> {
+        // Output format: "MONITOR = IMAGE" (one per line)
+        // e.g. "DP-1 = /path/to/image.png"
+        Ok(self
+            .query("listactive")?
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(monitor, path)| (monitor.trim().to_string(), path.trim().to_string()))
+            .collect())
+    }
+}
+
+impl Backend for Hyprpaper {
+    fn modes(&self) -> &'static [&'static str] {
+        &["contain", "cover", "tile"]
+    }
+
+    fn preload(&self, path: &str) -> Result<()> {
+        if self.loaded()?.iter().any(|loaded| loaded == path) {
+            return Ok(());
+        }
+        self.request(&format!("preload {}", path))
+    }
+
+    fn ready(&self) -> Result<()> {
+        self.query("listloaded").map(|_| ())
+    }
+
+    /// Unloads every image no monitor shows, including earlier previews.
+    fn unload_unused(&self) -> Result<()> {
+        let active = self.active()?;
+        for path in self.loaded()? {
+            if !active.iter().any(|(_, shown)| *shown == path) {
+                self.request(&format!("unload {}", path))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let target = hyprpaper_target(monitor, &entry.path, mode)
+            .ok_or_else(|| unsupported_mode("hyprpaper", mode))?;
+        self.request(&format!("wallpaper {}", target))
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        self.active()?
+            .into_iter()
+            .find(|(name, _)| name == monitor)
+            .map(|(_, path)| path)
+            .with_context(|| format!("Current wallpaper for monitor {} not found", monitor))
+    }
+}
+
+/// hyprpaper's "MONITOR,[MODE:]PATH" wallpaper value, `None` for modes it can't do.
+pub fn hyprpaper_target(monitor: &str, path: &str, mode: &str) -> Option<String> {
+    // Cover is hyprpaper's default, the others are a prefix on the path
+    let prefix = match mode {
+        "cover" => "",
+        "contain" => "contain:",
+        "tile" => "tile:",
+        _ => return None,
+    };
+    Some(format!("{},{}{}", monitor, prefix, path))
+}
+
+/// swww, through the `swww` client.
+struct Swww;
+
+impl Backend for Swww {
+    fn modes(&self) -> &'static [&'static str] {
+        &["contain", "cover", "fill"]
+    }
+
+    fn ready(&self) -> Result<()> {
+        run(Command::new("swww").arg("query")).map(|_| ())
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let path = entry.path.as_str();
+        let resize = match mode {
+            "contain" => "fit",
+            "cover" => "crop",
+            "fill" => "stretch",
+            _ => return Err(unsupported_mode("swww", mode)),
+        };
+        run(Command::new("swww")
+            .arg("img")
+            .arg("--outputs")
+            .arg(monitor)
+            .arg("--resize")
+            .arg(resize)
+            .arg(path))?;
+        Ok(())
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        let stdout = run(Command::new("swww").arg("query"))?;
+        // e.g. "DP-1: 1920x1080, scale: 1, currently displaying: image: /path/to/image.png"
+        // (older versions prefix the line with ": ")
+        for line in stdout.lines() {
+            let line = line.trim_start_matches(':').trim();
+            let Some((name, rest)) = line.split_once(':') else {
+                continue;
+            };
+            if name.trim() == monitor {
+                if let Some((_, path)) = rest.split_once("image: ") {
+                    return Ok(path.trim().to_string());
+                }
+            }
+        }
+        anyhow::bail!("Current wallpaper for monitor {} not found", monitor);
+    }
+}
+
+/// swaybg, one process per monitor that is replaced on every change.
+struct Swaybg;
+
+impl Backend for Swaybg {
+    fn modes(&self) -> &'static [&'static str] {
+        &["contain", "cover", "fill", "tile"]
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let path = entry.path.as_str();
+        let mode = match mode {
+            "contain" => "fit",
+            "cover" => "fill",
+            "fill" => "stretch",
+            "tile" => "tile",
+            _ => return Err(unsupported_mode("swaybg", mode)),
+        };
+        replace_process("swaybg", monitor, &["-o", monitor, "-i", path, "-m", mode])
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        anyhow::bail!("swaybg can't report the wallpaper on {}", monitor)
+    }
+}
+
+/// wpaperd, configured through its config file which it reloads on change.
+struct Wpaperd;
+
+impl Wpaperd {
+    fn config_path() -> Result<PathBuf> {
+        Ok(dirs::config_dir()
+            .context("Could not find config directory")?
+            .join("wpaperd")
+            .join("config.toml"))
+    }
+}
+
+impl Backend for Wpaperd {
+    fn modes(&self) -> &'static [&'static str] {
+        &["contain", "cover", "fill", "tile"]
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let path = entry.path.as_str();
+        let mode = match mode {
+            "contain" => "fit",
+            "cover" => "center",
+            "fill" => "stretch",
+            "tile" => "tile",
+            _ => return Err(unsupported_mode("wpaperd", mode)),
+        };
+        let config_path = Self::config_path()?;
+        let mut table: toml::Table = match fs::read_to_string(&config_path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", config_path.display()))?,
+            Err(_) => toml::Table::new(),
+        };
+        let section = table
+            .entry(monitor.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        let toml::Value::Table(section) = section else {
+            anyhow::bail!("[{}] in {} is not a table", monitor, config_path.display());
+        };
+        section.insert("path".to_string(), toml::Value::String(path.to_string()));
+        section.insert("mode".to_string(), toml::Value::String(mode.to_string()));
+
+        if let Some(dir) = config_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&config_path, toml::to_string(&table)?)
+            .with_context(|| format!("Failed to write {}", config_path.display()))
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        run(Command::new("wpaperctl").arg("get-wallpaper").arg(monitor))
+    }
+}
+
+/// mpvpaper, one process per monitor that is replaced on every change.
+struct Mpvpaper;
+
+impl Backend for Mpvpaper {
+    fn modes(&self) -> &'static [&'static str] {
+        &["contain", "cover", "fill"]
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let path = entry.path.as_str();
+        let fit = match mode {
+            "contain" => "panscan=0.0",
+            "cover" => "panscan=1.0",
+            "fill" => "keepaspect=no",
+            _ => return Err(unsupported_mode("mpvpaper", mode)),
+        };
+        let options = format!("no-audio loop {}", fit);
+        replace_process("mpvpaper", monitor, &["-o", &options, monitor, path])
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        anyhow::bail!("mpvpaper can't report the wallpaper on {}", monitor)
+    }
+}
+
+/// Starts `program` in the background for `monitor`, then stops the process
+/// it replaces, so the monitor is never left without a wallpaper.
+fn replace_process(program: &str, monitor: &str, args: &[&str]) -> Result<()> {
+    let pid_file = dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("hyprwallhaven-{}-{}.pid", program, monitor));
+    let previous = fs::read_to_string(&pid_file).unwrap_or_default();
+
+    // Started through sh so it is detached from us and never left as a zombie
+    let pid = run(Command::new("sh")
+        .arg("-c")
+        .arg("\"$0\" \"$@\" </dev/null >/dev/null 2>&1 & echo $!")
+        .arg(program)
+        .args(args)
+        .stdin(Stdio::null()))?;
+    fs::write(&pid_file, &pid)
+        .with_context(|| format!("Failed to write {}", pid_file.display()))?;
+
+    let previous = previous.trim();
+    // Only stop it if the PID still belongs to the program
+    let comm = fs::read_to_string(format!("/proc/{}/comm", previous)).unwrap_or_default();
+    if !previous.is_empty() && comm.trim() == program {
+        let _ = Command::new("kill").arg(previous).status();
+    }
+    Ok(())
+}
+
+/// `wallpaper_cmd`, for anything else: a string run once through `sh` with
+/// placeholders passed in environment variables, or an argv array run directly.
+struct Shell {
+    template: WallpaperCmd,
+}
+
+impl Backend for Shell {
+    fn modes(&self) -> &'static [&'static str] {
+        crate::config::WALLPAPER_MODES
+    }
+
+    fn apply(&self, monitor: &str, entry: &WallpaperEntry, mode: &str) -> Result<()> {
+        let values = Values::new(entry, monitor, mode);
+        let mut command = match &self.template {
+            WallpaperCmd::Shell(template) => {
+                let script = template::render_shell(template, &values);
+                println!("Executing: {}", script);
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(script).envs(values.env());
+                command
+            }
+            WallpaperCmd::Argv(template) => {
+                let argv: Vec<String> = template
+                    .iter()
+                    .map(|arg| template::render_arg(arg, &values))
+                    .collect();
+                println!("Executing: {:?}", argv);
+                let (program, args) = argv.split_first().context("wallpaper_cmd is empty")?;
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+        let status = command
+            .status()
+            .context("Failed to execute wallpaper_cmd")?;
+        if !status.success() {
+            anyhow::bail!("wallpaper_cmd failed ({})", status);
+        }
+        Ok(())
+    }
+
+    fn current(&self, monitor: &str) -> Result<String> {
+        anyhow::bail!("wallpaper_cmd can't report the wallpaper on {}", monitor)
+    }
+}
@@ -1,70 +1,508 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+/// Current config schema version. Bump this and append a step to `MIGRATIONS`
+/// whenever the layout of existing keys changes.
+pub const CONFIG_VERSION: u32 = 3;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Config {
-    pub wallpaper_cmd: String,
+    pub version: u32,
+    /// Program that draws the wallpaper: "hyprpaper", "swww", "swaybg", "wpaperd",
+    /// "mpvpaper" or "command" (runs `wallpaper_cmd`)
+    pub backend: String,
+    pub wallpaper_cmd: WallpaperCmd,
     pub save_dir: String,
     pub api_key: Option<String>,
+    /// Command printing the API key, e.g. "pass show wallhaven"
+    pub api_key_cmd: Option<String>,
+    /// File containing the API key
+    pub api_key_file: Option<String>,
+    /// Look up (and store) the API key in the freedesktop Secret Service
+    pub api_key_secret_service: bool,
     pub username: Option<String>,
     pub categories: String,
     pub purity: String,
     pub sorting: String,
     pub ratios: String,
-    #[serde(default = "default_wallpaper_mode")]
+    /// Minimum resolution of search results, e.g. "2560x1440"
+    pub atleast: Option<String>,
     pub wallpaper_mode: String,
+    /// How the desktop is cleared while previewing: "id:N", "free:N", "free:N-M",
+    /// "name:NAME", "hide" (park windows on a special workspace) or "none"
+    pub preview_workspace: String,
+    /// Path of Hyprland's control socket, defaults to
+    /// `$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE/.socket.sock`
+    pub hyprland_socket: Option<String>,
+    /// File name of downloaded wallpapers relative to `save_dir`, may include subdirectories
+    pub filename_template: String,
+    /// Number of wallpapers kept in the per-monitor history for undo/redo
+    pub history_size: usize,
+    /// Seconds `restore` waits for Hyprland and the wallpaper backend to come up
+    pub restore_timeout: u64,
+    /// File rendered once per monitor into the generated hyprlock config, see
+    /// `hyprlock::DEFAULT_TEMPLATE` for the built-in one
+    pub hyprlock_template: Option<String>,
+    /// Effects filled into the hyprlock template's `{blur_passes}`, `{blur_size}`,
+    /// `{brightness}`, `{contrast}` and `{vibrancy}`. Unset ones are left out.
+    pub hyprlock_blur_passes: u32,
+    pub hyprlock_blur_size: Option<u32>,
+    pub hyprlock_brightness: Option<f64>,
+    pub hyprlock_contrast: Option<f64>,
+    pub hyprlock_vibrancy: Option<f64>,
+    /// Also write a block without a monitor, showing the newest wallpaper on
+    /// monitors that have none saved
+    pub hyprlock_fallback: bool,
+    /// Give the lock screen a blurred, darkened copy of each wallpaper, made once
+    /// and cached next to it, instead of having hyprlock blur it at lock time
+    pub lock_image: bool,
+    /// Blur strength (Gaussian sigma in pixels) of the lock screen copy
+    pub lock_image_blur: f64,
+    /// Brightness of the lock screen copy, 1.0 keeps the original brightness
+    pub lock_image_brightness: f64,
+    /// Write `~/.config/hypr/hyprwallhaven-swaylock.conf`, for `swaylock -C`
+    pub swaylock: bool,
+    /// Write `~/.config/hypr/hyprwallhaven-gtklock.ini`, for `gtklock -c`
+    pub gtklock: bool,
+    /// Symlink kept pointing at the newest wallpaper, for a greetd greeter's
+    /// background setting, e.g. "/var/lib/greetd/wallpaper"
+    pub greeter_wallpaper: Option<String>,
+    /// Optional search query used by rotate (e.g. "nature")
+    pub query: Option<String>,
+    /// Per-monitor overrides, keyed by Hyprland monitor name (e.g. `[monitors.DP-1]`)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub monitors: BTreeMap<String, MonitorConfig>,
+    /// Where each top-level value came from. Not part of the file.
+    #[serde(skip)]
+    pub origins: Origins,
+    /// API key resolved on first use, see `Config::api_key`.
+    /// Shared between clones so the key command runs at most once.
+    #[serde(skip)]
+    resolved_api_key: Arc<OnceLock<Option<String>>>,
+}
+
+/// The command run by the "command" backend. Placeholders such as `{path}` are
+/// filled in by `template`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WallpaperCmd {
+    /// Run through `sh -c`, e.g. "swww img -o {monitor} {path}"
+    Shell(String),
+    /// Run directly, one argument per element
+    Argv(Vec<String>),
+}
+
+impl WallpaperCmd {
+    fn is_empty(&self) -> bool {
+        match self {
+            WallpaperCmd::Shell(cmd) => cmd.trim().is_empty(),
+            WallpaperCmd::Argv(argv) => argv.first().is_none_or(|p| p.trim().is_empty()),
+        }
+    }
+}
+
+/// Prefix for environment variables overriding config keys, e.g. `HYPRWALLHAVEN_PURITY`.
+pub const ENV_PREFIX: &str = "HYPRWALLHAVEN_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Env,
+    Cli,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Source::Default => "default",
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Cli => "cli",
+        })
+    }
+}
+
+/// Tracks the source of each top-level key, and the file values hidden by
+/// environment or CLI overrides so that saving never writes an override back.
+#[derive(Debug, Clone, Default)]
+pub struct Origins {
+    sources: BTreeMap<String, Source>,
+    // key -> (value in the file, if any; value of the override)
+    shadowed: BTreeMap<String, (Option<toml::Value>, toml::Value)>,
+}
+
+impl Origins {
+    pub fn source(&self, key: &str) -> Source {
+        self.sources.get(key).copied().unwrap_or(Source::Default)
+    }
+}
+
+/// Settings that can be overridden for a single monitor.
+/// Any field left unset falls back to the global value.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MonitorConfig {
+    pub wallpaper_cmd: Option<WallpaperCmd>,
+    pub query: Option<String>,
+    pub categories: Option<String>,
+    pub purity: Option<String>,
+    pub sorting: Option<String>,
+    pub ratios: Option<String>,
+    pub wallpaper_mode: Option<String>,
 }
 
-fn default_wallpaper_mode() -> String {
-    "contain".to_string()
+impl Config {
+    /// Whether any API key source is configured. Does not resolve the key.
+    pub fn has_api_key(&self) -> bool {
+        self.api_key.is_some()
+            || self.api_key_cmd.is_some()
+            || self.api_key_file.is_some()
+            || self.api_key_secret_service
+    }
+
+    /// The API key, resolved from the configured source the first time it is needed.
+    pub fn api_key(&self) -> Result<Option<String>> {
+        if let Some(key) = self.resolved_api_key.get() {
+            return Ok(key.clone());
+        }
+        let key = crate::secrets::resolve_api_key(self)?;
+        Ok(self.resolved_api_key.get_or_init(|| key).clone())
+    }
+
+    /// Sets a plain-text API key, replacing any previously resolved one.
+    pub fn set_api_key(&mut self, key: Option<String>) {
+        self.api_key = key;
+        self.resolved_api_key = Arc::default();
+    }
+
+    /// Returns a copy of the config with the overrides for `monitor_name` applied.
+    pub fn for_monitor(&self, monitor_name: &str) -> Config {
+        let mut config = self.clone();
+        let Some(overrides) = self.monitors.get(monitor_name) else {
+            return config;
+        };
+
+        if let Some(v) = &overrides.wallpaper_cmd {
+            config.wallpaper_cmd = v.clone();
+        }
+        if let Some(v) = &overrides.query {
+            config.query = Some(v.clone());
+        }
+        if let Some(v) = &overrides.categories {
+            config.categories = v.clone();
+        }
+        if let Some(v) = &overrides.purity {
+            config.purity = v.clone();
+        }
+        if let Some(v) = &overrides.sorting {
+            config.sorting = v.clone();
+        }
+        if let Some(v) = &overrides.ratios {
+            config.ratios = v.clone();
+        }
+        if let Some(v) = &overrides.wallpaper_mode {
+            config.wallpaper_mode = v.clone();
+        }
+        config
+    }
+
+    /// Ratios explicitly configured for a monitor, if any.
+    /// When unset, callers pick landscape/portrait from the monitor orientation.
+    pub fn monitor_ratios(&self, monitor_name: &str) -> Option<&str> {
+        self.monitors
+            .get(monitor_name)
+            .and_then(|m| m.ratios.as_deref())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            wallpaper_cmd: "hyprctl hyprpaper wallpaper \"%m,%f\"".to_string(),
+            version: CONFIG_VERSION,
+            backend: "hyprpaper".to_string(),
+            wallpaper_cmd: WallpaperCmd::Shell(
+                "hyprctl hyprpaper wallpaper \"{monitor},{path}\"".to_string(),
+            ),
             save_dir: "~/Pictures/Wallpapers/Wallhaven".to_string(),
             api_key: None,
+            api_key_cmd: None,
+            api_key_file: None,
+            api_key_secret_service: false,
             username: None,
             categories: "111".to_string(),
             purity: "100".to_string(),
             sorting: "hot".to_string(),
             ratios: "landscape".to_string(),
-            wallpaper_mode: default_wallpaper_mode(),
+            atleast: None,
+            wallpaper_mode: "contain".to_string(),
+            preview_workspace: "free:10".to_string(),
+            hyprland_socket: None,
+            filename_template: "wallhaven-{id}.{ext}".to_string(),
+            history_size: 50,
+            restore_timeout: 30,
+            hyprlock_template: None,
+            hyprlock_blur_passes: 0,
+            hyprlock_blur_size: None,
+            hyprlock_brightness: None,
+            hyprlock_contrast: None,
+            hyprlock_vibrancy: None,
+            hyprlock_fallback: false,
+            lock_image: false,
+            lock_image_blur: 20.0,
+            lock_image_brightness: 0.6,
+            swaylock: false,
+            gtklock: false,
+            greeter_wallpaper: None,
+            query: None,
+            monitors: BTreeMap::new(),
+            origins: Origins::default(),
+            resolved_api_key: Arc::default(),
         }
     }
 }
 
-pub fn load_config() -> Result<Config> {
-    let config_dir = dirs::config_dir()
+/// A single migration step, rewriting a table from version N to N+1.
+type Migration = fn(&mut toml::Table);
+
+/// Migration steps in order. `MIGRATIONS[0]` migrates version 1 to version 2, and so on.
+/// Files without a `version` key are treated as version 1.
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// v1 configs could use a literal `monitor_name` in `wallpaper_cmd` instead of `%m`.
+fn migrate_v1_to_v2(table: &mut toml::Table) {
+    if let Some(toml::Value::String(cmd)) = table.get_mut("wallpaper_cmd") {
+        *cmd = cmd.replace("monitor_name", "%m");
+    }
+}
+
+/// Commands v2 configs shipped with, which the hyprpaper backend replaces.
+const STOCK_HYPRPAPER_CMDS: &[&str] = &[
+    "hyprctl hyprpaper wallpaper \"%m,%f\"",
+    "hyprctl hyprpaper preload %f; hyprctl hyprpaper wallpaper \"%m,%f\"",
+];
+
+/// v3 adds `backend`. Configs with a custom `wallpaper_cmd` (also per monitor)
+/// keep running it through the "command" backend.
+fn migrate_v2_to_v3(table: &mut toml::Table) {
+    if table.contains_key("backend") {
+        return;
+    }
+    let stock = |value: Option<&toml::Value>| match value {
+        None => true,
+        Some(toml::Value::String(cmd)) => STOCK_HYPRPAPER_CMDS.contains(&cmd.trim()),
+        Some(_) => false,
+    };
+    let monitor_cmds = match table.get("monitors") {
+        Some(toml::Value::Table(monitors)) => monitors.values().all(|m| {
+            m.as_table()
+                .is_none_or(|m| !m.contains_key("wallpaper_cmd"))
+        }),
+        _ => true,
+    };
+    let backend = if stock(table.get("wallpaper_cmd")) && monitor_cmds {
+        "hyprpaper"
+    } else {
+        "command"
+    };
+    table.insert(
+        "backend".to_string(),
+        toml::Value::String(backend.to_string()),
+    );
+}
+
+fn config_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
         .context("Could not find config directory")?
-        .join("hypr");
-    let config_path = config_dir.join("hyprwallhaven.toml");
+        .join("hypr"))
+}
+
+pub fn config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("hyprwallhaven.toml"))
+}
 
-    if !config_path.exists() {
-        let config = Config::default();
-        fs::create_dir_all(&config_dir)?;
-        let toml_string = toml::to_string_pretty(&config)?;
-        fs::write(&config_path, toml_string)?;
-        return Ok(config);
+/// Runs all pending migrations on `table`. Returns the version the table was at before.
+fn migrate(table: &mut toml::Table) -> Result<u32> {
+    let from = match table.get("version") {
+        Some(v) => v
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .context("'version' must be a positive integer")?,
+        None => 1,
+    };
+
+    if from > CONFIG_VERSION {
+        anyhow::bail!(
+            "Config version {} is newer than supported version {}. Please update hyprwallhaven.",
+            from,
+            CONFIG_VERSION
+        );
     }
 
-    let content = fs::read_to_string(&config_path)?;
-    let config: Config = toml::from_str(&content)?;
+    for step in MIGRATIONS.iter().skip(from.saturating_sub(1) as usize) {
+        step(table);
+    }
+    table.insert(
+        "version".to_string(),
+        toml::Value::Integer(CONFIG_VERSION as i64),
+    );
+    Ok(from)
+}
+
+/// Converts a raw string into a value of the same type as `existing`.
+/// Strings are taken verbatim; anything else is parsed as a TOML value. Unset
+/// keys are parsed only if `key` (dotted for nested tables) then accepts the
+/// parsed value, e.g. an optional number, so optional strings such as "1234"
+/// stay strings.
+fn coerce_value(key: &str, existing: Option<&toml::Value>, raw: &str) -> Result<toml::Value> {
+    match existing {
+        Some(toml::Value::String(_)) => Ok(toml::Value::String(raw.to_string())),
+        None => {
+            let parsed = toml::from_str::<toml::Table>(&format!("v = {}", raw))
+                .ok()
+                .and_then(|mut t| t.remove("v"))
+                .filter(|v| !v.is_str());
+            let accepted = parsed.filter(|v| {
+                let nested = key.rsplit('.').fold(v.clone(), |value, part| {
+                    toml::Value::Table(toml::Table::from_iter([(part.to_string(), value)]))
+                });
+                Config::deserialize(nested).is_ok()
+            });
+            Ok(accepted.unwrap_or_else(|| toml::Value::String(raw.to_string())))
+        }
+        Some(_) => {
+            let parsed: toml::Table = toml::from_str(&format!("v = {}", raw))?;
+            Ok(parsed["v"].clone())
+        }
+    }
+}
+
+/// `HYPRWALLHAVEN_<KEY>` environment variables as (key, value) pairs.
+fn env_overrides() -> Vec<(String, String)> {
+    std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Applies overrides from one source on top of the table, remembering what they replaced.
+fn apply_overrides(
+    table: &mut toml::Table,
+    origins: &mut Origins,
+    overrides: &[(String, String)],
+    source: Source,
+) -> Result<()> {
+    // Keys missing from the file take their type from the defaults
+    let defaults = toml::Table::try_from(Config::default())?;
+    for (key, raw) in overrides {
+        if matches!(table.get(key), Some(toml::Value::Table(_))) {
+            anyhow::bail!("{} override for table '{}' is not supported", source, key);
+        }
+        let value = coerce_value(key, table.get(key).or_else(|| defaults.get(key)), raw)
+            .with_context(|| format!("Invalid {} value for '{}': {}", source, key, raw))?;
+        let file_value = match origins.shadowed.remove(key) {
+            Some((file_value, _)) => file_value,
+            None => table.get(key).cloned(),
+        };
+        origins
+            .shadowed
+            .insert(key.clone(), (file_value, value.clone()));
+        origins.sources.insert(key.clone(), source);
+        table.insert(key.clone(), value);
+    }
+    Ok(())
+}
+
+pub fn load_config() -> Result<Config> {
+    load_config_with(&[])
+}
+
+/// Loads the config, applying values in order of precedence:
+/// CLI overrides > `HYPRWALLHAVEN_<KEY>` environment variables > file > defaults.
+pub fn load_config_with(cli_overrides: &[(String, String)]) -> Result<Config> {
+    let config_path = config_path()?;
+
+    let mut from = CONFIG_VERSION;
+    let mut origins = Origins::default();
+    let mut table = if config_path.exists() {
+        let content = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let mut table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        from = migrate(&mut table)
+            .with_context(|| format!("Failed to migrate {}", config_path.display()))?;
+        for key in table.keys() {
+            origins.sources.insert(key.clone(), Source::File);
+        }
+        table
+    } else {
+        save_config(&Config::default())?;
+        toml::Table::try_from(Config::default())?
+    };
+
+    apply_overrides(&mut table, &mut origins, &env_overrides(), Source::Env)?;
+    apply_overrides(&mut table, &mut origins, cli_overrides, Source::Cli)?;
+
+    let mut config = Config::deserialize(toml::Value::Table(table))
+        .with_context(|| format!("Invalid config in {}", config_path.display()))?;
+
+    // Unknown keys are dropped by deserialization
+    let known = toml::Table::try_from(&config)?;
+    for (key, source) in &origins.sources {
+        if known.contains_key(key) {
+            continue;
+        }
+        match source {
+            Source::Cli => anyhow::bail!("Unknown config key '{}'", key),
+            Source::Env => eprintln!(
+                "Warning: Ignoring {}{}: unknown config key '{}'",
+                ENV_PREFIX,
+                key.to_uppercase(),
+                key
+            ),
+            _ => {}
+        }
+    }
+    config.origins = origins;
+
+    if from < CONFIG_VERSION {
+        // Keep the original file around in case the migration lost something
+        let backup_path = config_path.with_extension(format!("toml.v{}.bak", from));
+        fs::copy(&config_path, &backup_path)
+            .with_context(|| format!("Failed to back up config to {}", backup_path.display()))?;
+        save_config(&config)?;
+        eprintln!(
+            "Migrated config from version {} to {} (backup: {})",
+            from,
+            CONFIG_VERSION,
+            backup_path.display()
+        );
+    }
 
     Ok(config)
 }
 
 pub fn save_config(config: &Config) -> Result<()> {
-    let config_dir = dirs::config_dir()
-        .context("Could not find config directory")?
-        .join("hypr");
-    let config_path = config_dir.join("hyprwallhaven.toml");
-
-    let toml_string = toml::to_string_pretty(config)?;
-    fs::write(&config_path, toml_string)?;
+    fs::create_dir_all(config_dir()?)?;
+    let mut table = toml::Table::try_from(config)?;
+    // Keep environment/CLI overrides out of the file unless the value was changed since
+    for (key, (file_value, override_value)) in &config.origins.shadowed {
+        if table.get(key) != Some(override_value) {
+            continue;
+        }
+        match file_value {
+            Some(v) => table.insert(key.clone(), v.clone()),
+            None => table.remove(key),
+        };
+    }
+    let toml_string = toml::to_string_pretty(&table)?;
+    fs::write(config_path()?, toml_string)?;
     Ok(())
 }
 
@@ -76,3 +514,299 @@ pub fn expand_path(path: &str) -> PathBuf {
     }
     PathBuf::from(path)
 }
+
+pub const SORTING_OPTIONS: &[&str] = &[
+    "relevance",
+    "random",
+    "date_added",
+    "views",
+    "favorites",
+    "toplist",
+    "hot",
+];
+
+pub const WALLPAPER_MODES: &[&str] = &["contain", "cover", "fill", "tile"];
+
+pub const BACKENDS: &[&str] = &[
+    "hyprpaper",
+    "swww",
+    "swaybg",
+    "wpaperd",
+    "mpvpaper",
+    "command",
+];
+
+/// Checks a wallpaper mode exists and the backend can apply it.
+fn validate_mode(key: &str, value: &str, config: &Config, errors: &mut Vec<String>) {
+    if !WALLPAPER_MODES.contains(&value) {
+        validate_option(key, value, WALLPAPER_MODES, errors);
+        return;
+    }
+    let modes = crate::backend::from_config(config).modes();
+    if !modes.contains(&value) {
+        errors.push(format!(
+            "{}: \"{}\" is not supported by {} (supported: {})",
+            key,
+            value,
+            config.backend,
+            modes.join(", ")
+        ));
+    }
+}
+
+/// Checks a 3-digit on/off flag string such as categories ("111") or purity ("100").
+fn validate_flags(key: &str, value: &str, errors: &mut Vec<String>) {
+    if value.len() != 3 || !value.chars().all(|c| c == '0' || c == '1') {
+        errors.push(format!(
+            "{}: expected three 0/1 digits (e.g. \"100\"), got \"{}\"",
+            key, value
+        ));
+    }
+}
+
+fn validate_option(key: &str, value: &str, options: &[&str], errors: &mut Vec<String>) {
+    if !options.contains(&value) {
+        errors.push(format!(
+            "{}: \"{}\" is not one of {}",
+            key,
+            value,
+            options.join(", ")
+        ));
+    }
+}
+
+fn validate_range(key: &str, value: Option<f64>, min: f64, max: f64, errors: &mut Vec<String>) {
+    if let Some(value) = value {
+        if !(min..=max).contains(&value) {
+            errors.push(format!(
+                "{}: expected {} to {}, got {}",
+                key, min, max, value
+            ));
+        }
+    }
+}
+
+fn validate_filename_template(template: &str, errors: &mut Vec<String>) {
+    if !template.contains("{id}") {
+        errors.push("filename_template: must contain {id} to keep file names unique".to_string());
+    }
+    if template.split('/').any(|part| part == "..") {
+        errors.push("filename_template: must not contain '..'".to_string());
+    }
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            errors.push("filename_template: unclosed '{'".to_string());
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        if !crate::wallhaven::FILENAME_PLACEHOLDERS.contains(&name) {
+            errors.push(format!(
+                "filename_template: unknown placeholder {{{}}} (supported: {})",
+                name,
+                crate::wallhaven::FILENAME_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[start + len + 1..];
+    }
+}
+
+/// Parses a "WIDTHxHEIGHT" resolution.
+pub fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.trim().split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Returns a list of human-readable problems with the config. Empty means valid.
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if config.version != CONFIG_VERSION {
+        errors.push(format!(
+            "version: expected {}, got {}",
+            CONFIG_VERSION, config.version
+        ));
+    }
+    if config.backend == "command" && config.wallpaper_cmd.is_empty() {
+        errors.push("wallpaper_cmd: must not be empty".to_string());
+    }
+    if config.save_dir.trim().is_empty() {
+        errors.push("save_dir: must not be empty".to_string());
+    }
+    if config.ratios.trim().is_empty() {
+        errors.push("ratios: must not be empty".to_string());
+    }
+    if let Some(atleast) = &config.atleast {
+        if parse_resolution(atleast).is_none() {
+            errors.push(format!(
+                "atleast: expected WIDTHxHEIGHT (e.g. 2560x1440), got {:?}",
+                atleast
+            ));
+        }
+    }
+    validate_filename_template(&config.filename_template, &mut errors);
+    validate_range(
+        "hyprlock_brightness",
+        config.hyprlock_brightness,
+        0.0,
+        2.0,
+        &mut errors,
+    );
+    validate_range(
+        "hyprlock_contrast",
+        config.hyprlock_contrast,
+        0.0,
+        2.0,
+        &mut errors,
+    );
+    validate_range(
+        "hyprlock_vibrancy",
+        config.hyprlock_vibrancy,
+        0.0,
+        1.0,
+        &mut errors,
+    );
+    validate_range(
+        "lock_image_blur",
+        Some(config.lock_image_blur),
+        0.0,
+        200.0,
+        &mut errors,
+    );
+    validate_range(
+        "lock_image_brightness",
+        Some(config.lock_image_brightness),
+        0.0,
+        2.0,
+        &mut errors,
+    );
+    validate_flags("categories", &config.categories, &mut errors);
+    validate_flags("purity", &config.purity, &mut errors);
+    validate_option("sorting", &config.sorting, SORTING_OPTIONS, &mut errors);
+    validate_option("backend", &config.backend, BACKENDS, &mut errors);
+    validate_mode(
+        "wallpaper_mode",
+        &config.wallpaper_mode,
+        config,
+        &mut errors,
+    );
+    if let Err(e) = crate::hyprland::PreviewWorkspace::parse(&config.preview_workspace) {
+        errors.push(format!("preview_workspace: {}", e));
+    }
+
+    for (name, monitor) in &config.monitors {
+        let key = |field: &str| format!("monitors.{}.{}", name, field);
+        if let Some(v) = &monitor.categories {
+            validate_flags(&key("categories"), v, &mut errors);
+        }
+        if let Some(v) = &monitor.purity {
+            validate_flags(&key("purity"), v, &mut errors);
+        }
+        if let Some(v) = &monitor.sorting {
+            validate_option(&key("sorting"), v, SORTING_OPTIONS, &mut errors);
+        }
+        if let Some(v) = &monitor.wallpaper_mode {
+            validate_mode(&key("wallpaper_mode"), v, config, &mut errors);
+        }
+    }
+
+    errors
+}
+
+/// Renders the config as TOML with the API key masked.
+/// Each top-level value is annotated with where it came from (default, file, env or cli).
+pub fn show(config: &Config) -> Result<String> {
+    let mut config = config.clone();
+    if config.api_key.is_some() {
+        config.api_key = Some("********".to_string());
+    }
+
+    let table = toml::Table::try_from(&config)?;
+    let mut out = String::new();
+    let mut tables = toml::Table::new();
+    for (key, value) in table {
+        if value.is_table() {
+            tables.insert(key, value);
+            continue;
+        }
+        let mut line = toml::Table::new();
+        line.insert(key.clone(), value);
+        let source = match config.origins.source(&key) {
+            Source::Env => format!("env ({}{})", ENV_PREFIX, key.to_uppercase()),
+            source => source.to_string(),
+        };
+        out.push_str(&format!(
+            "{} # {}\n",
+            toml::to_string(&line)?.trim_end(),
+            source
+        ));
+    }
+    if !tables.is_empty() {
+        out.push('\n');
+        out.push_str(&toml::to_string_pretty(&tables)?);
+    }
+    Ok(out)
+}
+
+/// Looks up a value by dotted key, e.g. `purity` or `monitors.DP-1.ratios`.
+pub fn get_value(config: &Config, key: &str) -> Result<toml::Value> {
+    let table = toml::Table::try_from(config)?;
+    let mut parts = key.split('.');
+    let first = parts.next().unwrap_or_default();
+    let mut value = table
+        .get(first)
+        .with_context(|| format!("Unknown or unset config key '{}'", key))?;
+    for part in parts {
+        value = value
+            .get(part)
+            .with_context(|| format!("Unknown or unset config key '{}'", key))?;
+    }
+    Ok(value.clone())
+}
+
+/// Formats a value for printing: strings without quotes, tables as TOML.
+pub fn format_value(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Table(t) => toml::to_string_pretty(t)?.trim_end().to_string(),
+        other => other.to_string(),
+    })
+}
+
+/// Returns a copy of the config with `key` set to `raw`.
+/// Values are strings unless the existing value has another type, in which case
+/// `raw` is parsed as a TOML value. The result is validated before it is returned.
+pub fn set_value(config: &Config, key: &str, raw: &str) -> Result<Config> {
+    let mut table = toml::Table::try_from(config)?;
+    let path: Vec<&str> = key.split('.').collect();
+    let (last, parents) = path.split_last().context("Empty config key")?;
+
+    let mut current = &mut table;
+    for part in parents {
+        current = current
+            .entry(part.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("'{}' is not a table", part))?;
+    }
+
+    let value = coerce_value(key, current.get(*last), raw)
+        .with_context(|| format!("Invalid value for '{}': {}", key, raw))?;
+    current.insert(last.to_string(), value);
+
+    let mut updated = Config::deserialize(toml::Value::Table(table))
+        .with_context(|| format!("Invalid value for '{}': {}", key, raw))?;
+    updated.origins = config.origins.clone();
+
+    // Unknown keys are dropped by deserialization, so check the key survived
+    if get_value(&updated, key).is_err() {
+        anyhow::bail!("Unknown config key '{}'", key);
+    }
+
+    let errors = validate(&updated);
+    if !errors.is_empty() {
+        anyhow::bail!(errors.join("\n"));
+    }
+
+    Ok(updated)
+}
@@ -0,0 +1,141 @@
+use crate::config::{self, Config};
+use crate::hyprland::{self, Event, Monitor};
+use crate::state::{self, WallpaperEntry};
+use crate::{instance, wallhaven};
+use anyhow::{Context, Result};
+use rand::Rng;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Watches Hyprland's event socket and keeps every connected monitor's
+/// wallpaper applied: hot-plugged monitors get their saved wallpaper back
+/// (or a new one if they have none), everything is re-applied when the
+/// Hyprland config is reloaded, and monitors switch to the wallpaper pinned to
+/// the workspace they show.
+pub fn run(config: Config, overrides: &[(String, String)]) -> Result<()> {
+    let mut config = config;
+    // What each monitor shows, monitor name -> path. Starts out as the saved wallpapers.
+    let mut shown: HashMap<String, String> = state::load_state()?
+        .wallpapers
+        .into_iter()
+        .map(|(monitor, entry)| (monitor, entry.path))
+        .collect();
+    if let Err(e) = apply_to_all(&config, &mut shown, false) {
+        eprintln!("Warning: {}", e);
+    }
+
+    println!("Watching Hyprland for monitor and workspace changes...");
+    hyprland::listen_events(|event| {
+        let result = match event {
+            Event::MonitorAdded(name) => {
+                println!("Monitor added: {}", name);
+                shown.remove(&name);
+                connected_monitor(&name)
+                    .and_then(|monitor| apply_to_monitor(&config, &monitor, &mut shown, true))
+            }
+            Event::MonitorRemoved(name) => {
+                // Its state entry stays, so it gets the same wallpaper when it comes back
+                println!("Monitor removed: {}", name);
+                Ok(())
+            }
+            Event::ConfigReloaded => {
+                println!("Hyprland config reloaded, re-applying wallpapers");
+                match config::load_config_with(overrides) {
+                    Ok(reloaded) => config = reloaded,
+                    Err(e) => eprintln!("Warning: Keeping previous config: {}", e),
+                }
+                shown.clear();
+                apply_to_all(&config, &mut shown, true)
+            }
+            // Previews switch workspaces too, leave the wallpapers to the running session
+            Event::WorkspaceChanged if instance::is_interactive_running() => Ok(()),
+            Event::WorkspaceChanged => apply_to_all(&config, &mut shown, false),
+            Event::Other => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Warning: {}", e);
+        }
+    })?;
+    println!("Hyprland closed the event socket, exiting");
+    Ok(())
+}
+
+fn connected_monitor(name: &str) -> Result<Monitor> {
+    hyprland::get_monitors()?
+        .into_iter()
+        .find(|m| m.name == name)
+        .with_context(|| format!("Monitor {} is not connected", name))
+}
+
+fn apply_to_all(config: &Config, shown: &mut HashMap<String, String>, assign: bool) -> Result<()> {
+    for monitor in hyprland::get_monitors()? {
+        if let Err(e) = apply_to_monitor(config, &monitor, shown, assign) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Shows the wallpaper pinned to the monitor's active workspace, or else its
+/// saved wallpaper. Without either a new one is assigned if `assign` is set.
+/// Nothing is run if the monitor already shows it.
+fn apply_to_monitor(
+    config: &Config,
+    monitor: &Monitor,
+    shown: &mut HashMap<String, String>,
+    assign: bool,
+) -> Result<()> {
+    let state = state::load_state()?;
+    let exists = |entry: &&WallpaperEntry| Path::new(&entry.path).exists();
+    let pinned = state
+        .workspaces
+        .get(&monitor.active_workspace.name)
+        .filter(exists);
+    let saved = state.wallpapers.get(&monitor.name).filter(exists);
+
+    let path = match (pinned, saved) {
+        (Some(entry), _) if shown.get(&monitor.name) != Some(&entry.path) => {
+            println!(
+                "Showing {} pinned to workspace {} on {}",
+                entry.path, monitor.active_workspace.name, monitor.name
+            );
+            // Pinned wallpapers don't replace the monitor's own in state
+            crate::with_retry(&monitor.name, || {
+                crate::show_wallpaper(entry, config, &monitor.name)
+            })?;
+            entry.path.clone()
+        }
+        (None, Some(entry)) if shown.get(&monitor.name) != Some(&entry.path) => {
+            println!("Restoring {} on {}", entry.path, monitor.name);
+            crate::set_wallpaper_with_retry(entry, config, &monitor.name)?;
+            entry.path.clone()
+        }
+        (None, None) if assign => assign_new(config, monitor)?,
+        _ => return Ok(()),
+    };
+    crate::update_lock_images(config);
+    shown.insert(monitor.name.clone(), path);
+    Ok(())
+}
+
+/// Picks a random wallpaper for a monitor hyprwallhaven hasn't seen before,
+/// the same way `rotate` starts. Returns its path.
+fn assign_new(config: &Config, monitor: &Monitor) -> Result<String> {
+    let config = config.for_monitor(&monitor.name);
+    let ratio = crate::monitor_ratio(&config, monitor);
+
+    let page = rand::thread_rng().gen_range(1..=3);
+    let wallpapers =
+        wallhaven::search_wallpapers(&config, config.query.as_deref(), page, Some(&ratio))?;
+    if wallpapers.is_empty() {
+        anyhow::bail!("No wallpapers found for {}", monitor.name);
+    }
+    let index = rand::thread_rng().gen_range(0..wallpapers.len());
+    let chosen = wallhaven::get_wallpaper_info(&wallpapers[index].id, &config)?;
+
+    println!("Assigning {} to {}", chosen.id, monitor.name);
+    let entry = crate::download_to_library(&chosen, &config)?;
+    crate::set_wallpaper_with_retry(&entry, &config, &monitor.name)?;
+    crate::record_history(&entry, None, &config, &monitor.name);
+    Ok(entry.path)
+}
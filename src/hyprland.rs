@@ -1,17 +1,40 @@
+use crate::ipc;
 use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use std::process::Command;
+use std::io::{BufRead, BufReader};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ActiveWorkspace {
     pub id: i64,
+    #[serde(default)]
+    pub name: String,
 }
 
-#[derive(Deserialize, Debug)]
+impl ActiveWorkspace {
+    /// How dispatchers address this workspace. Named workspaces have negative
+    /// IDs, which Hyprland would read as a relative move, so they go by name.
+    pub fn selector(&self) -> String {
+        if self.id < 0 && !self.name.is_empty() {
+            format!("name:{}", self.name)
+        } else {
+            self.id.to_string()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct Monitor {
     pub name: String,
     pub width: i32,
     pub height: i32,
+    /// Position in the layout, in logical (scaled) pixels
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
     pub focused: bool,
     #[serde(default)]
     pub transform: i32,
@@ -19,6 +42,10 @@ pub struct Monitor {
     pub active_workspace: ActiveWorkspace,
 }
 
+fn default_scale() -> f64 {
+    1.0
+}
+
 impl Monitor {
     pub fn get_visual_dimensions(&self) -> (i32, i32) {
         // Hyprland transform values:
@@ -35,6 +62,13 @@ impl Monitor {
             _ => (self.width, self.height),
         }
     }
+
+    /// Size in the layout (logical pixels), as used by `x` and `y`.
+    pub fn get_logical_dimensions(&self) -> (f64, f64) {
+        let (width, height) = self.get_visual_dimensions();
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        (width as f64 / scale, height as f64 / scale)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -42,79 +76,266 @@ struct Workspace {
     id: i64,
 }
 
-pub fn get_active_monitor() -> Result<Monitor> {
-    let output = Command::new("hyprctl")
-        .arg("monitors")
-        .arg("-j")
-        .output()
-        .context("Failed to execute hyprctl monitors -j")?;
-
-    if !output.status.success() {
-        anyhow::bail!("hyprctl monitors -j failed");
+#[derive(Deserialize, Debug)]
+struct Client {
+    address: String,
+    workspace: Workspace,
+}
+
+/// Requests sent to Hyprland's control socket, the same ones `hyprctl` sends.
+enum Request<'a> {
+    Monitors,
+    Workspaces,
+    Clients,
+    Dispatch(&'a str, &'a str),
+}
+
+impl Request<'_> {
+    fn command(&self) -> String {
+        // "j/" asks for a JSON reply
+        match self {
+            Request::Monitors => "j/monitors".to_string(),
+            Request::Workspaces => "j/workspaces".to_string(),
+            Request::Clients => "j/clients".to_string(),
+            Request::Dispatch(dispatcher, arg) => format!("dispatch {} {}", dispatcher, arg),
+        }
     }
+}
+
+fn send(request: &Request) -> Result<String> {
+    ipc::request(&ipc::hyprland_socket()?, &request.command())
+}
 
-    let monitors: Vec<Monitor> =
-        serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
+/// Sends a request answered with JSON and parses the reply.
+fn query<T: DeserializeOwned>(request: Request) -> Result<T> {
+    let reply = send(&request)?;
+    serde_json::from_str(&reply).with_context(|| {
+        format!(
+            "Failed to parse Hyprland reply to {}: {}",
+            request.command(),
+            reply.trim()
+        )
+    })
+}
+
+pub fn get_monitors() -> Result<Vec<Monitor>> {
+    query(Request::Monitors)
+}
 
-    monitors
+pub fn get_active_monitor() -> Result<Monitor> {
+    get_monitors()?
         .into_iter()
         .find(|m| m.focused)
         .ok_or_else(|| anyhow::anyhow!("No focused monitor found"))
 }
 
 pub fn get_occupied_workspaces() -> Result<Vec<i64>> {
-    let output = Command::new("hyprctl")
-        .arg("workspaces")
-        .arg("-j")
-        .output()
-        .context("Failed to execute hyprctl workspaces -j")?;
-
-    if !output.status.success() {
-        anyhow::bail!("hyprctl workspaces -j failed");
-    }
-
-    let workspaces: Vec<Workspace> =
-        serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
-
+    let workspaces: Vec<Workspace> = query(Request::Workspaces)?;
     Ok(workspaces.into_iter().map(|w| w.id).collect())
 }
 
-pub fn dispatch_workspace(id: i64) -> Result<()> {
-    let output = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("workspace")
-        .arg(id.to_string())
-        .output()
-        .context("Failed to switch workspace")?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to switch workspace");
+fn dispatch(dispatcher: &str, arg: &str) -> Result<()> {
+    // Hyprland answers "ok" on success and an error message otherwise
+    let reply = send(&Request::Dispatch(dispatcher, arg))?;
+    if !reply.trim().eq_ignore_ascii_case("ok") {
+        anyhow::bail!("dispatch {} {} failed: {}", dispatcher, arg, reply.trim());
     }
     Ok(())
 }
 
-pub fn get_current_wallpaper(monitor_name: &str) -> Result<String> {
-    let output = Command::new("hyprctl")
-        .arg("hyprpaper")
-        .arg("listactive")
-        .output()
-        .context("Failed to execute hyprctl hyprpaper listactive")?;
+pub fn dispatch_workspace(id: i64) -> Result<()> {
+    dispatch("workspace", &id.to_string()).context("Failed to switch workspace")
+}
+
+fn get_clients() -> Result<Vec<Client>> {
+    query(Request::Clients)
+}
 
-    if !output.status.success() {
-        anyhow::bail!("hyprctl hyprpaper listactive failed");
+/// Special workspace windows are parked on with `preview_workspace = "hide"`.
+const HIDE_WORKSPACE: &str = "special:hyprwallhaven";
+
+/// How the desktop is cleared while previewing wallpapers (`preview_workspace`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewWorkspace {
+    /// `id:N`: switch to workspace N
+    Fixed(i64),
+    /// `free:N` or `free:N-M`: switch to the first unoccupied workspace from N (up to M)
+    FirstFree(i64, Option<i64>),
+    /// `name:NAME`: switch to a named workspace
+    Named(String),
+    /// `hide`: move the windows of the current workspace to a special workspace
+    Hide,
+    /// `none`: leave the desktop alone
+    Off,
+}
+
+impl PreviewWorkspace {
+    pub fn parse(value: &str) -> Result<Self> {
+        let parse_id = |s: &str| {
+            s.trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|id| *id > 0)
+                .with_context(|| format!("invalid workspace ID {:?} in {:?}", s, value))
+        };
+        let (kind, arg) = value.split_once(':').unwrap_or((value, ""));
+        match (kind.trim(), arg) {
+            ("none", "") => Ok(PreviewWorkspace::Off),
+            ("hide", "") => Ok(PreviewWorkspace::Hide),
+            ("id", id) => Ok(PreviewWorkspace::Fixed(parse_id(id)?)),
+            ("free", range) => {
+                let (start, end) = match range.split_once('-') {
+                    Some((start, "")) => (parse_id(start)?, None),
+                    Some((start, end)) => (parse_id(start)?, Some(parse_id(end)?)),
+                    None => (parse_id(range)?, None),
+                };
+                if end.is_some_and(|end| end < start) {
+                    anyhow::bail!("empty workspace range in {:?}", value);
+                }
+                Ok(PreviewWorkspace::FirstFree(start, end))
+            }
+            ("name", name) if !name.trim().is_empty() => {
+                Ok(PreviewWorkspace::Named(name.trim().to_string()))
+            }
+            _ => anyhow::bail!(
+                "expected one of none, hide, id:N, free:N, free:N-M, name:NAME; got {:?}",
+                value
+            ),
+        }
     }
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    // Output format: "MONITOR = IMAGE" (one per line)
-    // e.g. "DP-1 = /path/to/image.png"
+/// What `clear_desktop` changed, undone by `restore_desktop`.
+#[derive(Debug)]
+pub struct ClearedDesktop {
+    workspace: ActiveWorkspace,
+    switched: bool,
+    /// Monitor that had focus before, if `monitor` was focused to switch its workspace
+    refocus: Option<String>,
+    hidden_windows: Vec<String>,
+}
 
-    for line in stdout.lines() {
-        if let Some((mon, path)) = line.split_once('=') {
-            if mon.trim() == monitor_name {
-                return Ok(path.trim().to_string());
+/// Clears the desktop on `monitor` for previewing wallpapers.
+pub fn clear_desktop(strategy: &PreviewWorkspace, monitor: &Monitor) -> Result<ClearedDesktop> {
+    let workspace_id = monitor.active_workspace.id;
+    let mut cleared = ClearedDesktop {
+        workspace: monitor.active_workspace.clone(),
+        switched: false,
+        refocus: None,
+        hidden_windows: Vec::new(),
+    };
+    // Workspace switches apply to the focused monitor
+    let switches = !matches!(strategy, PreviewWorkspace::Off | PreviewWorkspace::Hide);
+    if switches && !monitor.focused {
+        let focused = get_active_monitor().ok().map(|m| m.name);
+        dispatch("focusmonitor", &monitor.name).context("Failed to focus monitor")?;
+        cleared.refocus = focused.filter(|name| *name != monitor.name);
+    }
+    let result = (|| -> Result<()> {
+        match strategy {
+            PreviewWorkspace::Off => {}
+            PreviewWorkspace::Fixed(id) => {
+                dispatch_workspace(*id)?;
+                cleared.switched = true;
+            }
+            PreviewWorkspace::FirstFree(start, end) => {
+                let occupied = get_occupied_workspaces().unwrap_or_default();
+                let mut id = *start;
+                while occupied.contains(&id) {
+                    id += 1;
+                }
+                if end.is_some_and(|end| id > end) {
+                    eprintln!(
+                        "Warning: No free workspace in {}-{}, previewing on the current one",
+                        start,
+                        end.unwrap_or_default()
+                    );
+                } else {
+                    dispatch_workspace(id)?;
+                    cleared.switched = true;
+                }
+            }
+            PreviewWorkspace::Named(name) => {
+                dispatch("workspace", &format!("name:{}", name))
+                    .context("Failed to switch workspace")?;
+                cleared.switched = true;
+            }
+            PreviewWorkspace::Hide => {
+                for client in get_clients()? {
+                    if client.workspace.id != workspace_id {
+                        continue;
+                    }
+                    let target = format!("{},address:{}", HIDE_WORKSPACE, client.address);
+                    match dispatch("movetoworkspacesilent", &target) {
+                        Ok(()) => cleared.hidden_windows.push(client.address),
+                        Err(e) => {
+                            eprintln!("Warning: Failed to hide window {}: {}", client.address, e)
+                        }
+                    }
+                }
             }
         }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        if let Some(monitor) = &cleared.refocus {
+            let _ = dispatch("focusmonitor", monitor);
+        }
+        return Err(e);
+    }
+    Ok(cleared)
+}
+
+/// Switches back to the original workspace, brings back hidden windows and
+/// gives focus back to the monitor that had it.
+pub fn restore_desktop(cleared: &ClearedDesktop) -> Result<()> {
+    let mut result = Ok(());
+    for address in &cleared.hidden_windows {
+        let target = format!("{},address:{}", cleared.workspace.selector(), address);
+        if let Err(e) = dispatch("movetoworkspacesilent", &target) {
+            result = Err(e).context("Failed to bring back hidden windows");
+        }
+    }
+    if cleared.switched {
+        dispatch("workspace", &cleared.workspace.selector())
+            .context("Failed to switch workspace")?;
+    }
+    if let Some(monitor) = &cleared.refocus {
+        dispatch("focusmonitor", monitor).context("Failed to focus monitor")?;
+    }
+    result
+}
+
+/// Events from Hyprland's event socket that hyprwallhaven reacts to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    MonitorAdded(String),
+    MonitorRemoved(String),
+    ConfigReloaded,
+    /// A workspace was shown or moved to another monitor
+    WorkspaceChanged,
+    Other,
+}
+
+impl Event {
+    fn parse(line: &str) -> Event {
+        let (name, data) = line.split_once(">>").unwrap_or((line, ""));
+        match name {
+            "monitoradded" => Event::MonitorAdded(data.to_string()),
+            "monitorremoved" => Event::MonitorRemoved(data.to_string()),
+            "configreloaded" => Event::ConfigReloaded,
+            "workspace" | "moveworkspace" => Event::WorkspaceChanged,
+            _ => Event::Other,
+        }
     }
+}
 
-    anyhow::bail!("Current wallpaper for monitor {} not found", monitor_name);
+/// Calls `handler` for every event until Hyprland closes the event socket.
+pub fn listen_events(mut handler: impl FnMut(Event)) -> Result<()> {
+    let stream = ipc::connect(&ipc::event_socket()?)?;
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("Failed to read Hyprland event")?;
+        handler(Event::parse(&line));
+    }
+    Ok(())
 }
@@ -1,29 +1,72 @@
-use crate::state::State;
+use crate::config::{self, Config};
+use crate::lockscreen;
+use crate::state::{State, WallpaperEntry};
+use crate::template::{self, Values};
 use anyhow::{Context, Result};
 use std::fs;
 
-pub fn update_hyprlock_config(state: &State) -> Result<()> {
+/// One `background` block per monitor, used unless `hyprlock_template` is set.
+/// Lines whose placeholders have no value are left out.
+pub const DEFAULT_TEMPLATE: &str = "# {url}
+background {
+    monitor = {monitor}
+    path = {path}
+    color = rgba(25, 20, 20, 1.0)
+    blur_passes = {blur_passes}
+    blur_size = {blur_size}
+    brightness = {brightness}
+    contrast = {contrast}
+    vibrancy = {vibrancy}
+}
+
+";
+
+pub fn update_hyprlock_config(state: &State, config: &Config) -> Result<()> {
     let config_dir = dirs::config_dir()
         .context("Could not find config directory")?
         .join("hypr");
     let lock_conf_path = config_dir.join("hyprwallhaven-hyprlock.conf");
 
+    let template = match &config.hyprlock_template {
+        Some(path) => {
+            let path = config::expand_path(path);
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+        }
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+
     let mut content = String::from("# Auto-generated by hyprwallhaven\n\n");
 
-    for (monitor, path) in &state.wallpapers {
-        content.push_str("background {\n");
-        content.push_str(&format!("    monitor = {}\n", monitor));
-        content.push_str(&format!("    path = {}\n", path));
-        content.push_str("    color = rgba(25, 20, 20, 1.0)\n");
-        content.push_str("    blur_passes = 0\n"); 
-        content.push_str("}\n\n");
+    // Blocks without a monitor apply to every monitor, so the fallback comes
+    // first and the per-monitor blocks are drawn over it
+    if config.hyprlock_fallback {
+        if let Some(entry) = state.newest() {
+            content.push_str(&render(&template, entry, "", config));
+        }
     }
 
-    // Add a fallback for unconfigured monitors?
-    // Maybe not, to avoid overriding user's manual config if they mix and match.
-    // But usually people want coverage.
-    // Let's stick to explicit monitors we know about.
+    let mut monitors: Vec<_> = state.wallpapers.iter().collect();
+    monitors.sort_by_key(|(monitor, _)| monitor.as_str());
+    for (monitor, entry) in monitors {
+        content.push_str(&render(&template, entry, monitor, config));
+    }
 
     fs::write(&lock_conf_path, content)?;
     Ok(())
 }
+
+fn render(template: &str, entry: &WallpaperEntry, monitor: &str, config: &Config) -> String {
+    let option = |value: Option<f64>| value.map(|v| v.to_string());
+    let values = Values::new(entry, monitor, entry.mode.as_deref().unwrap_or_default())
+        .with("path", Some(lockscreen::image_for(entry, config)))
+        .with("blur_passes", Some(config.hyprlock_blur_passes.to_string()))
+        .with(
+            "blur_size",
+            config.hyprlock_blur_size.map(|v| v.to_string()),
+        )
+        .with("brightness", option(config.hyprlock_brightness))
+        .with("contrast", option(config.hyprlock_contrast))
+        .with("vibrancy", option(config.hyprlock_vibrancy));
+    template::render_lines(template, &values)
+}
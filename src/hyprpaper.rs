@@ -0,0 +1,49 @@
+use crate::backend::hyprpaper_target;
+use crate::state::State;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Writes a hyprpaper config with the saved wallpapers, so hyprpaper can show
+/// them from the start when its own config sources this file.
+pub fn update_hyprpaper_config(state: &State) -> Result<()> {
+    let config_dir = dirs::config_dir()
+        .context("Could not find config directory")?
+        .join("hypr");
+    let conf_path = config_dir.join("hyprwallhaven-hyprpaper.conf");
+
+    // hyprpaper refuses to start on images it can't load
+    let mut monitors: Vec<_> = state
+        .wallpapers
+        .iter()
+        .filter(|(_, entry)| Path::new(&entry.path).exists())
+        .collect();
+    monitors.sort_by_key(|(monitor, _)| monitor.as_str());
+
+    let mut content = String::from("# Auto-generated by hyprwallhaven\n\n");
+
+    let mut preloaded: Vec<&str> = Vec::new();
+    for (_, entry) in &monitors {
+        if !preloaded.contains(&entry.path.as_str()) {
+            content.push_str(&format!("preload = {}\n", entry.path));
+            preloaded.push(&entry.path);
+        }
+    }
+    content.push('\n');
+
+    for (monitor, entry) in &monitors {
+        // Modes hyprpaper can't do (saved with another backend) fall back to its default
+        let mode = entry.mode.as_deref().unwrap_or("cover");
+        let target = hyprpaper_target(monitor, &entry.path, mode)
+            .or_else(|| hyprpaper_target(monitor, &entry.path, "cover"))
+            .unwrap_or_default();
+        if let Some(url) = &entry.source_url {
+            content.push_str(&format!("# {}\n", url));
+        }
+        content.push_str(&format!("wallpaper = {}\n", target));
+    }
+
+    fs::write(&conf_path, content)
+        .with_context(|| format!("Failed to write {}", conf_path.display()))?;
+    Ok(())
+}
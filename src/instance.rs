@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// Per-user lock held by the interactive session (menu, rotate, set), and by
+/// commands queued behind it (restore, undo, redo). The file contains the PID of
+/// the holder and whether it is interactive, so a second invocation can cancel it.
+pub struct SessionLock {
+    file: File,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        // Don't leave a PID behind that could be reused by another process
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// The process holding the session lock.
+struct Holder {
+    pid: String,
+    interactive: bool,
+}
+
+/// Reads the holder from the lock file. Returns `None` if the recorded PID
+/// doesn't hold the lock (nobody does, or the new holder hasn't written its PID
+/// yet) or isn't a running hyprwallhaven process.
+fn read_holder() -> Option<Holder> {
+    let content = fs::read_to_string(lock_path()).ok()?;
+    let (pid, kind) = content.trim().split_once(' ')?;
+    let comm = |pid: &str| fs::read_to_string(format!("/proc/{}/comm", pid)).ok();
+    if pid == std::process::id().to_string() || lock_owner()? != pid || comm(pid)? != comm("self")?
+    {
+        return None;
+    }
+    Some(Holder {
+        pid: pid.to_string(),
+        interactive: kind == "interactive",
+    })
+}
+
+/// PID of the process holding the lock file's flock, from `/proc/locks`.
+fn lock_owner() -> Option<String> {
+    let metadata = fs::metadata(lock_path()).ok()?;
+    let dev = metadata.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let file = format!("{:02x}:{:02x}:{}", major, minor, metadata.ino());
+    let locks = fs::read_to_string("/proc/locks").ok()?;
+    // e.g. "1: FLOCK  ADVISORY  WRITE 1234 fe:00:5678 0 EOF", waiters start with "->"
+    locks.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [_, "FLOCK", _, _, pid, id, ..] if *id == file => Some(pid.to_string()),
+            _ => None,
+        }
+    })
+}
+
+fn lock_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("hyprwallhaven.lock")
+}
+
+fn open_lock_file() -> Result<File> {
+    let path = lock_path();
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+fn claim(mut file: File, interactive: bool) -> Result<SessionLock> {
+    let kind = if interactive { "interactive" } else { "queued" };
+    file.set_len(0)?;
+    write!(file, "{} {}", std::process::id(), kind)?;
+    file.flush()?;
+    Ok(SessionLock { file })
+}
+
+fn try_lock(interactive: bool) -> Result<Option<SessionLock>> {
+    let file = open_lock_file()?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(claim(file, interactive)?)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e).context("Failed to lock session"),
+    }
+}
+
+/// Takes the session lock for an interactive session, or returns `None` if
+/// another session holds it.
+pub fn try_acquire() -> Result<Option<SessionLock>> {
+    try_lock(true)
+}
+
+/// Whether an interactive session (e.g. a menu previewing wallpapers) is
+/// running right now. Reads the lock file without taking the lock, so it never
+/// gets in the way of a session starting. Queued commands such as `restore`
+/// don't count.
+pub fn is_interactive_running() -> bool {
+    read_holder().is_some_and(|holder| holder.interactive)
+}
+
+/// Takes the session lock, waiting for a running interactive session to finish first.
+/// Used to queue non-interactive commands that change wallpapers.
+pub fn acquire_blocking() -> Result<SessionLock> {
+    if let Some(lock) = try_lock(false)? {
+        return Ok(lock);
+    }
+    println!("Waiting for the running hyprwallhaven session to finish...");
+    let file = open_lock_file()?;
+    file.lock().context("Failed to lock session")?;
+    claim(file, false)
+}
+
+/// Cancels the running interactive session by sending SIGTERM to it and its
+/// children (the open fuzzel menu). Commands queued behind the lock, such as
+/// `restore` at login, are left alone.
+pub fn cancel_running() -> Result<()> {
+    // The holder may have just taken the lock and not written its PID yet
+    let holder = (0..10).find_map(|attempt| {
+        if attempt > 0 {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        read_holder()
+    });
+    let holder = holder.context("Running session did not record its PID")?;
+    if !holder.interactive {
+        anyhow::bail!(
+            "Another hyprwallhaven command (restore, undo or redo) is running, try again once it has finished"
+        );
+    }
+    let pid = holder.pid.as_str();
+    println!("Cancelling the running hyprwallhaven session");
+
+    // Collect children first, they get reparented once the session exits
+    let children = Command::new("pgrep")
+        .arg("-P")
+        .arg(pid)
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default();
+
+    let status = Command::new("kill")
+        .arg("-TERM")
+        .arg(pid)
+        .status()
+        .context("Failed to execute kill")?;
+    if !status.success() {
+        anyhow::bail!("Failed to signal session {}", pid);
+    }
+
+    for child in children.split_whitespace() {
+        let _ = Command::new("kill").arg("-TERM").arg(child).status();
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Control socket path from the `hyprland_socket` config value, set once at startup.
+static SOCKET_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_socket_override(path: PathBuf) {
+    let _ = SOCKET_OVERRIDE.set(path);
+}
+
+/// Directory holding the sockets of the running Hyprland instance,
+/// `$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE`.
+fn instance_dir() -> Result<PathBuf> {
+    if let Some(socket) = SOCKET_OVERRIDE.get() {
+        return Ok(socket.parent().map(Path::to_path_buf).unwrap_or_default());
+    }
+    let hypr_dir = dirs::runtime_dir()
+        .context("XDG_RUNTIME_DIR is not set")?
+        .join("hypr");
+    if let Ok(signature) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+        return Ok(hypr_dir.join(signature));
+    }
+    // Not set e.g. in systemd units that don't import the session environment:
+    // fall back to the most recently started instance
+    latest_instance(&hypr_dir).with_context(|| {
+        format!(
+            "HYPRLAND_INSTANCE_SIGNATURE is not set and no Hyprland instance was found in {}. \
+             Set hyprland_socket to the path of its .socket.sock",
+            hypr_dir.display()
+        )
+    })
+}
+
+fn latest_instance(hypr_dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(hypr_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join(".socket.sock").exists())
+        .max_by_key(|dir| dir.metadata().and_then(|m| m.modified()).ok())
+}
+
+/// Hyprland's request socket (what `hyprctl` talks to).
+pub fn hyprland_socket() -> Result<PathBuf> {
+    match SOCKET_OVERRIDE.get() {
+        Some(socket) => Ok(socket.clone()),
+        None => Ok(instance_dir()?.join(".socket.sock")),
+    }
+}
+
+/// Hyprland's event socket, streaming one `EVENT>>DATA` line per event.
+pub fn event_socket() -> Result<PathBuf> {
+    Ok(instance_dir()?.join(".socket2.sock"))
+}
+
+/// hyprpaper's request socket, next to Hyprland's.
+pub fn hyprpaper_socket() -> Result<PathBuf> {
+    Ok(instance_dir()?.join(".hyprpaper.sock"))
+}
+
+pub fn connect(socket: &Path) -> Result<UnixStream> {
+    UnixStream::connect(socket)
+        .with_context(|| format!("Failed to connect to {}", socket.display()))
+}
+
+/// Sends a single request and returns the reply. The server closes the
+/// connection once it has answered.
+pub fn request(socket: &Path, command: &str) -> Result<String> {
+    let mut stream = connect(socket)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream
+        .write_all(command.as_bytes())
+        .with_context(|| format!("Failed to send {:?} to {}", command, socket.display()))?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .with_context(|| format!("Failed to read reply to {:?}", command))?;
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hyprland::{self, Event};
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Mutex, OnceLock};
+
+    const MONITORS: &str = r#"[
+        {"name": "DP-1", "width": 2560, "height": 1440, "focused": false,
+         "activeWorkspace": {"id": 2, "name": "2"}},
+        {"name": "HDMI-A-1", "width": 1920, "height": 1080, "x": 2560, "scale": 1.5,
+         "transform": 1, "focused": true, "activeWorkspace": {"id": 5, "name": "web"}}
+    ]"#;
+
+    const EVENTS: &str = "workspace>>web\nmonitoradded>>DP-2\nmonitorremoved>>DP-2\n\
+                          configreloaded>>\nmoveworkspace>>web,DP-1\nactivewindow>>kitty,~\n";
+
+    /// Requests the fake Hyprland received, in order.
+    static REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    /// Starts a fake Hyprland with a request and an event socket in a temporary
+    /// directory, once per test run, and points the client at it.
+    fn fake_hyprland() {
+        static STARTED: OnceLock<()> = OnceLock::new();
+        STARTED.get_or_init(|| {
+            let dir =
+                std::env::temp_dir().join(format!("hyprwallhaven-ipc-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let requests = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+            let events = UnixListener::bind(dir.join(".socket2.sock")).unwrap();
+            super::set_socket_override(dir.join(".socket.sock"));
+
+            std::thread::spawn(move || {
+                for stream in requests.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut buf = [0; 1024];
+                    let len = stream.read(&mut buf).unwrap();
+                    let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                    let reply = match request.as_str() {
+                        "j/monitors" => MONITORS,
+                        "dispatch workspace 3" => "ok",
+                        _ => "Invalid workspace",
+                    };
+                    REQUESTS.lock().unwrap().push(request);
+                    stream.write_all(reply.as_bytes()).unwrap();
+                }
+            });
+            std::thread::spawn(move || {
+                for stream in events.incoming() {
+                    stream.unwrap().write_all(EVENTS.as_bytes()).unwrap();
+                }
+            });
+        });
+    }
+
+    fn received(request: &str) -> bool {
+        REQUESTS.lock().unwrap().iter().any(|r| r == request)
+    }
+
+    #[test]
+    fn monitors() {
+        fake_hyprland();
+        let monitors = hyprland::get_monitors().unwrap();
+        assert!(received("j/monitors"));
+        assert_eq!(monitors.len(), 2);
+        assert_eq!(monitors[0].name, "DP-1");
+        assert_eq!(monitors[0].scale, 1.0);
+
+        let active = hyprland::get_active_monitor().unwrap();
+        assert_eq!(active.name, "HDMI-A-1");
+        assert_eq!((active.x, active.scale), (2560, 1.5));
+        assert_eq!(active.active_workspace.name, "web");
+        assert_eq!(active.get_logical_dimensions(), (720.0, 1280.0));
+    }
+
+    #[test]
+    fn dispatch_workspace() {
+        fake_hyprland();
+        hyprland::dispatch_workspace(3).unwrap();
+        assert!(received("dispatch workspace 3"));
+
+        let error = hyprland::dispatch_workspace(99).unwrap_err();
+        assert!(received("dispatch workspace 99"));
+        assert!(
+            format!("{:#}", error).contains("Invalid workspace"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn events() {
+        fake_hyprland();
+        let mut events = Vec::new();
+        hyprland::listen_events(|event| events.push(event)).unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::WorkspaceChanged,
+                Event::MonitorAdded("DP-2".to_string()),
+                Event::MonitorRemoved("DP-2".to_string()),
+                Event::ConfigReloaded,
+                Event::WorkspaceChanged,
+                Event::Other,
+            ]
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::RgbImage;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+const QUALITY: u8 = 92;
+
+/// Saves `image` as a JPEG. It is written to a temporary file first and renamed
+/// into place, so an interrupted write never leaves a truncated image at `path`
+/// that would later pass for a finished one.
+pub fn save(image: &RgbImage, path: &Path) -> Result<()> {
+    let tmp_path = path.with_extension(format!("jpg.tmp.{}", std::process::id()));
+    let result = (|| {
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        let mut writer = BufWriter::new(file);
+        image
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, QUALITY))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path.display()))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
@@ -0,0 +1,76 @@
+use crate::config::{self, Config};
+use crate::lockscreen;
+use crate::state::State;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+fn generated_path(name: &str) -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .context("Could not find config directory")?
+        .join("hypr")
+        .join(name))
+}
+
+/// Writes a swaylock config with one image per monitor, for `swaylock -C`.
+/// The newest wallpaper comes first and covers monitors without their own.
+pub fn update_swaylock_config(state: &State, config: &Config) -> Result<()> {
+    let conf_path = generated_path("hyprwallhaven-swaylock.conf")?;
+
+    let scaling = match config.wallpaper_mode.as_str() {
+        "contain" => "fit",
+        "tile" => "tile",
+        _ => "fill",
+    };
+    let mut content = format!("# Auto-generated by hyprwallhaven\n\nscaling={}\n", scaling);
+    if let Some(entry) = state.newest() {
+        content.push_str(&format!("image={}\n", lockscreen::image_for(entry, config)));
+    }
+
+    let mut monitors: Vec<_> = state.wallpapers.iter().collect();
+    monitors.sort_by_key(|(monitor, _)| monitor.as_str());
+    for (monitor, entry) in monitors {
+        let image = lockscreen::image_for(entry, config);
+        content.push_str(&format!("image={}:{}\n", monitor, image));
+    }
+
+    fs::write(&conf_path, content)?;
+    Ok(())
+}
+
+/// Writes a gtklock config with the newest wallpaper as background, for
+/// `gtklock -c`. gtklock shows the same image on every monitor.
+pub fn update_gtklock_config(state: &State, config: &Config) -> Result<()> {
+    let conf_path = generated_path("hyprwallhaven-gtklock.ini")?;
+
+    let mut content = String::from("# Auto-generated by hyprwallhaven\n\n[main]\n");
+    if let Some(entry) = state.newest() {
+        content.push_str(&format!(
+            "background={}\n",
+            lockscreen::image_for(entry, config)
+        ));
+    }
+
+    fs::write(&conf_path, content)?;
+    Ok(())
+}
+
+/// Points the `greeter_wallpaper` symlink at the newest wallpaper, for greeters
+/// that read their background from a fixed path.
+pub fn update_greeter_link(state: &State, config: &Config, link: &str) -> Result<()> {
+    let Some(entry) = state.newest() else {
+        return Ok(());
+    };
+    let link = config::expand_path(link);
+    let target = lockscreen::image_for(entry, config);
+
+    // Swap in a new link by renaming, so the greeter never sees it missing
+    let mut tmp = link.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let _ = fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(&target, &tmp)
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+    fs::rename(&tmp, &link).with_context(|| format!("Failed to replace {}", link.display()))?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::config::Config;
+use crate::jpeg;
+use crate::state::{State, WallpaperEntry};
+use anyhow::{Context, Result};
+use image::imageops;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+fn copy_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .context("Could not find cache directory")?
+        .join("hyprwallhaven")
+        .join("lock"))
+}
+
+/// Where the lock screen copy of `image` is saved, in the cache directory. The
+/// name includes a hash of the image path and the effect settings, so changing
+/// them creates a fresh copy.
+pub fn copy_path(image: &Path, config: &Config) -> Result<PathBuf> {
+    let stem = image
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut hasher = DefaultHasher::new();
+    image.hash(&mut hasher);
+    Ok(copy_dir()?.join(format!(
+        "{}-{:016x}-{}-{}.jpg",
+        stem,
+        hasher.finish(),
+        config.lock_image_blur,
+        config.lock_image_brightness
+    )))
+}
+
+/// The lock screen copy of `image`, if one is cached and newer than the image.
+pub fn cached_copy(image: &Path, config: &Config) -> Option<PathBuf> {
+    let path = copy_path(image, config).ok()?;
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    (modified(&path)? >= modified(image)?).then_some(path)
+}
+
+/// Returns a blurred and darkened copy of `image` for the lock screen, creating
+/// it unless an up-to-date one is already cached.
+pub fn processed_copy(image: &Path, config: &Config) -> Result<PathBuf> {
+    if let Some(path) = cached_copy(image, config) {
+        return Ok(path);
+    }
+    let path = copy_path(image, config)?;
+    fs::create_dir_all(copy_dir()?)?;
+
+    let source =
+        image::open(image).with_context(|| format!("Failed to open {}", image.display()))?;
+    let mut processed = imageops::fast_blur(&source.to_rgb8(), config.lock_image_blur as f32);
+    let brightness = config.lock_image_brightness as f32;
+    for pixel in processed.pixels_mut() {
+        pixel.0 = pixel
+            .0
+            .map(|c| (c as f32 * brightness).round().min(255.0) as u8);
+    }
+
+    jpeg::save(&processed, &path)?;
+    Ok(path)
+}
+
+/// The image a lock screen should show for `entry`: its cached copy if
+/// `lock_image` is on, otherwise (or until the copy is made) the wallpaper itself.
+/// Copies are made by `processed_copy` once a wallpaper is kept, never here, so
+/// previews don't wait for them.
+pub fn image_for(entry: &WallpaperEntry, config: &Config) -> String {
+    let copy = config
+        .lock_image
+        .then(|| cached_copy(Path::new(&entry.path), config))
+        .flatten();
+    match copy {
+        Some(copy) => copy.to_string_lossy().to_string(),
+        None => entry.path.clone(),
+    }
+}
+
+/// Removes the copies of wallpapers no longer in the state (set, pinned or in
+/// a history), as well as those made with other effect settings.
+pub fn remove_unused_copies(state: &State, config: &Config) -> Result<()> {
+    let history = state.history.values().flat_map(|h| &h.entries);
+    let keep: HashSet<PathBuf> = state
+        .wallpapers
+        .values()
+        .chain(state.workspaces.values())
+        .chain(history)
+        .filter_map(|entry| copy_path(Path::new(&entry.path), config).ok())
+        .collect();
+    let entries = match fs::read_dir(copy_dir()?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        // Leaves alone temp files of copies being written
+        if path.extension().is_some_and(|ext| ext == "jpg") && !keep.contains(&path) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
@@ -1,54 +1,156 @@
+mod backend;
 mod config;
+mod daemon;
 mod hyprland;
 mod hyprlock;
+mod hyprpaper;
+mod instance;
+mod ipc;
+mod jpeg;
+mod lockers;
+mod lockscreen;
+mod secrets;
+mod session;
+mod span;
 mod state;
+mod template;
 mod ui;
 mod wallhaven;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use config::{expand_path, load_config};
+use config::{expand_path, load_config_with};
 use rand::Rng;
-use std::path::PathBuf;
+use std::path::Path;
 use std::process::Command;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use wallhaven::{download_wallpaper, get_wallpaper_info, search_wallpapers};
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Override a config value for this run (takes precedence over HYPRWALLHAVEN_<KEY>)
+    #[arg(short = 'o', long = "option", global = true, value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+}
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))
 }
 #[derive(Subcommand)]
 enum Commands {
     /// Rotate to a random wallpaper from the Hot list
-    Rotate,
+    Rotate {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
     /// Open the Fuzzel menu
-    Menu,
-    /// Search Wallhaven (opens browser)
-    Search { query: String },
+    Menu {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Search Wallhaven (opens browser, or searches in place with monitor options)
+    Search {
+        query: String,
+        #[command(flatten)]
+        target: TargetArgs,
+    },
     /// Set a specific wallpaper by ID or URL
-    Set { id_or_url: String },
+    Set {
+        id_or_url: String,
+        #[command(flatten)]
+        target: TargetArgs,
+    },
     /// Init systemd units
     Init,
     /// Restore wallpapers from state
     Restore,
+    /// Watch Hyprland and apply wallpapers to monitors as they are plugged in
+    Daemon,
+    /// Show the wallpaper set on each monitor
+    Status,
+    /// Go back to the previous wallpaper on the focused monitor
+    Undo {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Re-apply the wallpaper undone last on the focused monitor
+    Redo {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Inspect or change the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the current configuration
+    Show,
+    /// Print a single value (use dots for tables, e.g. monitors.DP-1.ratios)
+    Get { key: String },
+    /// Set a value and save the configuration
+    Set { key: String, value: String },
+    /// Check the configuration for errors
+    Validate,
+    /// Open the configuration in $EDITOR and validate it afterwards
+    Edit,
 }
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let mut config = load_config()?;
+    // Config commands must work even when the config file is broken
+    if let Some(Commands::Config { action }) = &cli.command {
+        return handle_config_command(action, &cli.overrides);
+    }
+    let mut config = load_config_with(&cli.overrides)?;
+    if let Some(socket) = &config.hyprland_socket {
+        ipc::set_socket_override(expand_path(socket));
+    }
+
+    // Only one interactive session at a time. Starting another (e.g. pressing the
+    // keybind twice) cancels the running one; wallpaper-changing commands queue behind it.
+    let interactive = match &cli.command {
+        Some(Commands::Menu { .. } | Commands::Rotate { .. } | Commands::Set { .. }) => true,
+        Some(Commands::Search { target, .. }) => target.is_set(),
+        _ => false,
+    };
+    let _session = match &cli.command {
+        _ if interactive => match instance::try_acquire()? {
+            Some(lock) => {
+                session::install_signal_handler();
+                Some(lock)
+            }
+            None => {
+                instance::cancel_running()?;
+                return Ok(());
+            }
+        },
+        Some(Commands::Restore | Commands::Undo { .. } | Commands::Redo { .. }) => {
+            Some(instance::acquire_blocking()?)
+        }
+        _ => None,
+    };
+
     match cli.command {
-        Some(Commands::Rotate) => {
-            rotate_wallpaper(&mut config)?;
+        Some(Commands::Rotate { target }) => {
+            rotate_wallpaper(&mut config, &Targets::resolve(&target)?)?;
         }
-        Some(Commands::Menu) => {
-            handle_menu(&mut config)?;
+        Some(Commands::Menu { target }) => {
+            handle_menu(&mut config, &Targets::resolve(&target)?)?;
         }
-        Some(Commands::Search { query }) => {
-            let url = format!("https://wallhaven.cc/search?q={}", query);
-            open::that(url)?;
+        Some(Commands::Search { query, target }) => {
+            if target.is_set() {
+                search_interactive(&mut config, Some(query), &Targets::resolve(&target)?)?;
+            } else {
+                let url = format!("https://wallhaven.cc/search?q={}", query);
+                open::that(url)?;
+            }
         }
-        Some(Commands::Set { id_or_url }) => {
+        Some(Commands::Set { id_or_url, target }) => {
             // Extract ID if it's a URL
             let id = if id_or_url.contains("wallhaven.cc/w/") {
                 id_or_url
@@ -59,7 +161,7 @@ fn main() -> Result<()> {
             } else {
                 id_or_url
             };
-            set_specific_wallpaper(&id, &config)?;
+            set_specific_wallpaper(&id, &config, &Targets::resolve(&target)?)?;
         }
         Some(Commands::Init) => {
             println!("Please create a systemd timer to run 'wallhaven-cli rotate' periodically.");
@@ -67,6 +169,19 @@ fn main() -> Result<()> {
         Some(Commands::Restore) => {
             restore_wallpapers(&config)?;
         }
+        Some(Commands::Daemon) => {
+            daemon::run(config, &cli.overrides)?;
+        }
+        Some(Commands::Status) => {
+            print_status()?;
+        }
+        Some(Commands::Undo { target }) => {
+            step_history(&config, &Targets::resolve(&target)?, false)?;
+        }
+        Some(Commands::Redo { target }) => {
+            step_history(&config, &Targets::resolve(&target)?, true)?;
+        }
+        Some(Commands::Config { .. }) => {} // Handled above
         None => {
             use clap::CommandFactory;
             Cli::command().print_help()?;
@@ -74,370 +189,997 @@ fn main() -> Result<()> {
     }
     Ok(())
 }
-fn rotate_wallpaper(global_config: &mut config::Config) -> Result<()> {
-    let mut config_val = global_config.clone();
-    let config = &mut config_val;
+fn handle_config_command(action: &ConfigCommand, overrides: &[(String, String)]) -> Result<()> {
+    let load = || load_config_with(overrides);
+    match action {
+        ConfigCommand::Show => {
+            print!("{}", config::show(&load()?)?);
+        }
+        ConfigCommand::Get { key } => {
+            let value = config::get_value(&load()?, key)?;
+            println!("{}", config::format_value(&value)?);
+        }
+        ConfigCommand::Set { key, value } => {
+            let updated = config::set_value(&load()?, key, value)?;
+            config::save_config(&updated)?;
+        }
+        ConfigCommand::Validate => {
+            validate_config_file(overrides)?;
+        }
+        ConfigCommand::Edit => {
+            let path = config::config_path()?;
+            if !path.exists() {
+                // Creates the file with defaults
+                load()?;
+            }
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            // Run through the shell so EDITOR may contain arguments (e.g. "code --wait")
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$1\"", editor))
+                .arg("sh")
+                .arg(&path)
+                .status()?;
+            if !status.success() {
+                anyhow::bail!("Editor exited with {}", status);
+            }
+            validate_config_file(overrides)?;
+        }
+    }
+    Ok(())
+}
+fn validate_config_file(overrides: &[(String, String)]) -> Result<()> {
+    let path = config::config_path()?;
+    let errors = config::validate(&load_config_with(overrides)?);
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        anyhow::bail!("{} has {} error(s)", path.display(), errors.len());
+    }
+    println!("{} is valid", path.display());
+    Ok(())
+}
+/// Wallpapers rotated on one or more monitors that share a search.
+struct RotateGroup {
+    targets: Targets,
+    config: config::Config,
+    ratio: String,
+    wallpapers: Vec<wallhaven::Wallpaper>,
+    index: usize,
+    current: Option<state::WallpaperEntry>,
+}
 
-    // Get active monitor info
-    let monitor = hyprland::get_active_monitor().unwrap_or_else(|e| {
-        eprintln!(
-            "Warning: Could not get active monitor: {}. Defaulting to config.",
-            e
-        );
-        hyprland::Monitor {
-            name: "".to_string(),
-            width: 1920,
-            height: 1080,
-            focused: true,
-            transform: 0,
-            active_workspace: hyprland::ActiveWorkspace { id: 1 },
+impl RotateGroup {
+    fn search(&self) -> Result<Vec<wallhaven::Wallpaper>> {
+        // We use a random page to get more variety
+        let page = rand::thread_rng().gen_range(1..=3);
+        search_wallpapers(
+            &self.config,
+            self.config.query.as_deref(),
+            page,
+            Some(&self.ratio),
+        )
+    }
+
+    /// Searches again after a settings change, keeping the current batch if nothing is found.
+    fn refresh(&mut self) {
+        match self.search() {
+            Ok(new_batch) => {
+                if new_batch.is_empty() {
+                    eprintln!("No wallpapers found with new settings.");
+                } else {
+                    self.wallpapers = new_batch;
+                    self.index = rand::thread_rng().gen_range(0..self.wallpapers.len());
+                }
+            }
+            Err(e) => eprintln!("Failed to refresh wallpapers: {}", e),
         }
-    });
-    let original_wallpaper = hyprland::get_current_wallpaper(&monitor.name).ok();
-    let original_workspace_id = monitor.active_workspace.id;
-    let (width, height) = monitor.get_visual_dimensions();
-    let ratio = if width >= height {
-        "landscape"
+    }
+
+    /// Downloads the selected wallpaper and shows it on the group's monitors.
+    fn show(&mut self, global_config: &config::Config) -> Result<()> {
+        let chosen_summary = &self.wallpapers[self.index];
+        let chosen = get_wallpaper_info(&chosen_summary.id, &self.config)?;
+        println!("Downloading {}...", chosen.id);
+        let entry = download_to_library(&chosen, &self.config)?;
+        self.targets.apply(&entry, global_config)?;
+        self.current = Some(entry);
+        Ok(())
+    }
+
+    fn keep(&self, originals: &[Original], global_config: &config::Config) {
+        if let Some(ref entry) = self.current {
+            self.targets.keep(entry, originals, global_config);
+        }
+    }
+
+    fn pin(&self, originals: &[Original], global_config: &config::Config) -> Result<()> {
+        match self.current {
+            Some(ref entry) => self.targets.pin(entry, originals, global_config),
+            None => Ok(()),
+        }
+    }
+}
+
+fn rotate_wallpaper(global_config: &mut config::Config, targets: &Targets) -> Result<()> {
+    // One search per monitor so each gets a correctly oriented image,
+    // or a single one for the first monitor when they all show the same
+    let group_targets: Vec<Targets> = if targets.same_on_all {
+        vec![targets.clone()]
     } else {
-        "portrait"
+        targets
+            .monitors
+            .iter()
+            .map(|m| Targets::single(m.clone()))
+            .collect()
     };
-    println!(
-        "Detecting monitor: {} ({}) - Ratio: {}",
-        monitor.name,
-        if monitor.name.is_empty() {
-            "Fallback"
-        } else {
-            "Active"
-        },
-        ratio
-    );
-    // 1. Search for wallpapers (Hot list)
-    // We use a random page to get more variety
-    let page = rand::thread_rng().gen_range(1..=3);
-    let mut wallpapers = search_wallpapers(config, None, page, Some(ratio))?;
-    if wallpapers.is_empty() {
-        eprintln!("No wallpapers found.");
-        return Ok(());
-    }
-    // Find empty workspace
-    let occupied = hyprland::get_occupied_workspaces().unwrap_or_default();
-    let mut empty_workspace_id = 10;
-    while occupied.contains(&empty_workspace_id) {
-        empty_workspace_id += 1;
+    let mut groups = Vec::new();
+    for group_targets in group_targets {
+        let monitor = group_targets.primary();
+        let config = group_targets.search_config(global_config);
+        let ratio = group_targets.ratio(&config);
+        println!(
+            "Detecting monitor: {} ({}) - Ratio: {}",
+            monitor.name,
+            if monitor.name.is_empty() {
+                "Fallback"
+            } else {
+                "Active"
+            },
+            ratio
+        );
+        let mut group = RotateGroup {
+            targets: group_targets,
+            config,
+            ratio,
+            wallpapers: Vec::new(),
+            index: 0,
+            current: None,
+        };
+        // 1. Search for wallpapers (Hot list)
+        group.wallpapers = group.search()?;
+        if group.wallpapers.is_empty() {
+            eprintln!("No wallpapers found.");
+            return Ok(());
+        }
+        group.index = rand::thread_rng().gen_range(0..group.wallpapers.len());
+        groups.push(group);
     }
-    // Switch to empty workspace
-    hyprland::dispatch_workspace(empty_workspace_id)?;
+    let originals = targets.current_wallpapers(global_config);
+    let session =
+        session::PreviewSession::begin(global_config, targets.primary(), originals.clone())?;
     // 4. Interactive Loop
     // For rotate, we pick a random one initially.
     // We can iterate or re-roll. Let's stick to "Random" meaning "pick random from list".
     // "Next" meaning "next in list".
-    let mut index = rand::thread_rng().gen_range(0..wallpapers.len());
-    let mut total = wallpapers.len();
-    let mut _current_set_path = None;
+    // The menu follows the first group, the others step along with it.
     // Set first immediately
-    {
-        let chosen_summary = &wallpapers[index];
-        let chosen = get_wallpaper_info(&chosen_summary.id, config)?;
-        let ext = chosen.path.split('.').last().unwrap_or("jpg");
-        let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-        let save_path = expand_path(&config.save_dir).join(&filename);
-        println!("Downloading {} to {:?}", chosen.id, save_path);
-        download_wallpaper(&chosen.path, &save_path)?;
-        set_system_wallpaper(&save_path, config, &monitor.name)?;
-        _current_set_path = Some(save_path);
+    for group in &mut groups {
+        group.show(global_config)?;
     }
     'nav_loop: loop {
         use ui::NavAction;
+        let lead = &groups[0];
         match ui::show_search_nav_menu(
-            index,
-            total,
-            config.api_key.is_some(),
-            &config.categories,
-            &config.purity,
-            &config.sorting,
+            lead.index,
+            lead.wallpapers.len(),
+            lead.config.has_api_key(),
+            &lead.config.categories,
+            &lead.config.purity,
+            &lead.config.sorting,
         )? {
             NavAction::SettingsCategory => {
-                if let Some(new_cats) = ui::show_categories_menu(&config.categories)? {
-                    if config.categories != new_cats {
-                        config.categories = new_cats;
-                        let page = rand::thread_rng().gen_range(1..=3);
-                        match search_wallpapers(config, None, page, Some(ratio)) {
-                            Ok(new_batch) => {
-                                if new_batch.is_empty() {
-                                    eprintln!("No wallpapers found with new settings.");
-                                } else {
-                                    wallpapers = new_batch;
-                                    total = wallpapers.len();
-                                    index = rand::thread_rng().gen_range(0..total);
-                                }
-                            }
-                            Err(e) => eprintln!("Failed to refresh wallpapers: {}", e),
+                if let Some(new_cats) = ui::show_categories_menu(&lead.config.categories)? {
+                    if lead.config.categories != new_cats {
+                        for group in &mut groups {
+                            group.config.categories = new_cats.clone();
+                            group.refresh();
                         }
                     }
                 }
             }
             NavAction::SettingsPurity => {
-                if let Some(new_purity) = ui::show_purity_menu(&config.purity)? {
-                    if config.purity != new_purity {
-                        config.purity = new_purity;
-                        let page = rand::thread_rng().gen_range(1..=3);
-                        match search_wallpapers(config, None, page, Some(ratio)) {
-                            Ok(new_batch) => {
-                                if new_batch.is_empty() {
-                                    eprintln!("No wallpapers found with new settings.");
-                                } else {
-                                    wallpapers = new_batch;
-                                    total = wallpapers.len();
-                                    index = rand::thread_rng().gen_range(0..total);
-                                }
-                            }
-                            Err(e) => eprintln!("Failed to refresh wallpapers: {}", e),
+                if let Some(new_purity) = ui::show_purity_menu(&lead.config.purity)? {
+                    if lead.config.purity != new_purity {
+                        for group in &mut groups {
+                            group.config.purity = new_purity.clone();
+                            group.refresh();
                         }
                     }
                 }
             }
             NavAction::SettingsSorting => {
-                if let Some(new_sorting) = ui::show_sorting_menu(&config.sorting)? {
-                    if config.sorting != new_sorting {
-                        config.sorting = new_sorting;
-                        let page = rand::thread_rng().gen_range(1..=3);
-                        match search_wallpapers(config, None, page, Some(ratio)) {
-                            Ok(new_batch) => {
-                                if new_batch.is_empty() {
-                                    eprintln!("No wallpapers found with new settings.");
-                                } else {
-                                    wallpapers = new_batch;
-                                    total = wallpapers.len();
-                                    index = rand::thread_rng().gen_range(0..total);
-                                }
-                            }
-                            Err(e) => eprintln!("Failed to refresh wallpapers: {}", e),
+                if let Some(new_sorting) = ui::show_sorting_menu(&lead.config.sorting)? {
+                    if lead.config.sorting != new_sorting {
+                        for group in &mut groups {
+                            group.config.sorting = new_sorting.clone();
+                            group.refresh();
                         }
                     }
                 }
             }
             NavAction::Next => {
-                index = (index + 1) % total;
+                for group in &mut groups {
+                    group.index = (group.index + 1) % group.wallpapers.len();
+                }
             }
             NavAction::Prev => {
-                if index == 0 {
-                    index = total - 1;
-                } else {
-                    index -= 1;
+                for group in &mut groups {
+                    if group.index == 0 {
+                        group.index = group.wallpapers.len() - 1;
+                    } else {
+                        group.index -= 1;
+                    }
                 }
             }
             NavAction::Random => {
-                index = rand::thread_rng().gen_range(0..total);
+                for group in &mut groups {
+                    group.index = rand::thread_rng().gen_range(0..group.wallpapers.len());
+                }
             }
             NavAction::OpenInBrowser => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                let chosen_summary = &wallpapers[index];
+                session.keep()?;
+                let chosen_summary = &lead.wallpapers[lead.index];
                 println!("Opening in browser: {}", chosen_summary.short_url);
                 open::that(&chosen_summary.short_url)?;
+                for group in &groups {
+                    group.keep(&originals, global_config);
+                }
                 std::process::exit(0);
             }
             NavAction::Done => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
+                session.keep()?;
+                for group in &groups {
+                    group.keep(&originals, global_config);
+                }
                 std::process::exit(0);
             }
-            NavAction::Cancel | NavAction::None => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                if let Some(ref orig_path) = original_wallpaper {
-                    println!("Restoring original wallpaper: {}", orig_path);
-                    set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
+            NavAction::PinToWorkspace => {
+                session.keep()?;
+                for group in &groups {
+                    group.pin(&originals, global_config)?;
                 }
+                std::process::exit(0);
+            }
+            NavAction::Cancel | NavAction::None => {
+                session.cancel()?;
                 break 'nav_loop;
             }
         }
         // Apply new selection
-        let chosen_summary = &wallpapers[index];
-        let chosen = get_wallpaper_info(&chosen_summary.id, config)?;
-        let ext = chosen.path.split('.').last().unwrap_or("jpg");
-        let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-        let save_path = expand_path(&config.save_dir).join(&filename);
-        download_wallpaper(&chosen.path, &save_path)?;
-        set_system_wallpaper(&save_path, config, &monitor.name)?;
-        _current_set_path = Some(save_path);
+        for group in &mut groups {
+            group.show(global_config)?;
+        }
     }
     Ok(())
 }
-fn set_specific_wallpaper(id: &str, config: &config::Config) -> Result<()> {
-    let wallpaper = get_wallpaper_info(id, config)?;
-    // Get active monitor info
-    let monitor = hyprland::get_active_monitor().unwrap_or_else(|_| hyprland::Monitor {
-        name: "".to_string(),
-        width: 1920,
-        height: 1080,
-        focused: true,
-        transform: 0,
-        active_workspace: hyprland::ActiveWorkspace { id: 1 },
-    });
-    let original_wallpaper = hyprland::get_current_wallpaper(&monitor.name).ok();
-    let original_workspace_id = monitor.active_workspace.id;
-    // Switch to empty workspace
-    let occupied = hyprland::get_occupied_workspaces().unwrap_or_default();
-    let mut empty_workspace_id = 10;
-    while occupied.contains(&empty_workspace_id) {
-        empty_workspace_id += 1;
+/// Monitor options shared by rotate, set, search, menu, undo and redo.
+#[derive(clap::Args, Clone, Default)]
+struct TargetArgs {
+    /// Act on this monitor instead of the focused one
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["all_monitors", "same_on_all", "span"])]
+    monitor: Option<String>,
+    /// Act on every connected monitor (rotate picks a separate image for each)
+    #[arg(long)]
+    all_monitors: bool,
+    /// Put the same wallpaper on every connected monitor
+    #[arg(long)]
+    same_on_all: bool,
+    /// Span one wide wallpaper across all connected monitors
+    #[arg(long, conflicts_with = "same_on_all")]
+    span: bool,
+}
+impl TargetArgs {
+    fn is_set(&self) -> bool {
+        self.monitor.is_some() || self.all_monitors || self.same_on_all || self.span
     }
-    hyprland::dispatch_workspace(empty_workspace_id)?;
-    let ext = wallpaper.path.split('.').last().unwrap_or("jpg");
-    let filename = format!("wallhaven-{}.{}", wallpaper.id, ext);
-    let save_path = expand_path(&config.save_dir).join(&filename);
-    println!("Downloading {}...", wallpaper.id);
-    download_wallpaper(&wallpaper.path, &save_path)?;
-    set_system_wallpaper(&save_path, config, &monitor.name)?;
-    // Preview Loop
-    loop {
-        use ui::NavAction;
-        match ui::show_preview_menu()? {
-            NavAction::Done => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                std::process::exit(0);
+}
+/// Monitors a flow applies wallpapers to. Menus, previews and search ratios
+/// follow the first one.
+#[derive(Clone)]
+struct Targets {
+    monitors: Vec<hyprland::Monitor>,
+    same_on_all: bool,
+    /// Set when one wallpaper is cut into pieces for all monitors
+    span: Option<span::Layout>,
+}
+/// A monitor and its own wallpaper from before the flow started.
+type Original = (String, Option<state::WallpaperEntry>);
+impl Targets {
+    fn resolve(args: &TargetArgs) -> Result<Targets> {
+        if args.all_monitors || args.same_on_all || args.span {
+            let mut monitors = hyprland::get_monitors()?;
+            if monitors.is_empty() {
+                anyhow::bail!("No monitors found");
             }
-            NavAction::Cancel => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                if let Some(ref orig_path) = original_wallpaper {
-                    println!("Restoring original wallpaper: {}", orig_path);
-                    set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
-                }
-                break;
+            // Focused monitor first
+            monitors.sort_by_key(|m| !m.focused);
+            let span = if args.span {
+                Some(span::Layout::new(&monitors)?)
+            } else {
+                None
+            };
+            return Ok(Targets {
+                monitors,
+                same_on_all: args.same_on_all || args.span,
+                span,
+            });
+        }
+        let monitor = match &args.monitor {
+            Some(name) => {
+                let monitors = hyprland::get_monitors()?;
+                let connected: Vec<&str> = monitors.iter().map(|m| m.name.as_str()).collect();
+                let connected = connected.join(", ");
+                monitors
+                    .into_iter()
+                    .find(|m| &m.name == name)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Monitor {} not found (connected: {})", name, connected)
+                    })?
             }
-            NavAction::OpenInBrowser => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                println!("Opening in browser: {}", wallpaper.short_url);
-                open::that(&wallpaper.short_url)?;
-                std::process::exit(0);
+            None => focused_monitor(),
+        };
+        Ok(Targets::single(monitor))
+    }
+    fn single(monitor: hyprland::Monitor) -> Targets {
+        Targets {
+            monitors: vec![monitor],
+            same_on_all: true,
+            span: None,
+        }
+    }
+    fn primary(&self) -> &hyprland::Monitor {
+        &self.monitors[0]
+    }
+    /// Config for searching: the first monitor's, asking for images large
+    /// enough for the whole layout when spanning.
+    fn search_config(&self, global_config: &config::Config) -> config::Config {
+        let mut config = global_config.for_monitor(&self.primary().name);
+        if let Some(ref layout) = self.span {
+            let (width, height) = layout.resolution();
+            config.atleast = Some(format!("{}x{}", width, height));
+        }
+        config
+    }
+    /// Search ratio: wide ratios when spanning, otherwise the first monitor's.
+    fn ratio(&self, config: &config::Config) -> String {
+        match self.span {
+            Some(ref layout) => layout.ratios(),
+            None => monitor_ratio(config, self.primary()),
+        }
+    }
+    /// What a wallpaper looks like on one of the monitors: a piece of it when spanning.
+    fn entry_for(&self, entry: &state::WallpaperEntry, monitor: &str) -> state::WallpaperEntry {
+        let mut entry = entry.clone();
+        if self.span.is_some() {
+            entry.path = span::piece_path(Path::new(&entry.path), monitor)
+                .to_string_lossy()
+                .to_string();
+        }
+        entry
+    }
+    /// Shows the wallpaper on every target monitor.
+    fn apply(&self, entry: &state::WallpaperEntry, config: &config::Config) -> Result<()> {
+        if let Some(ref layout) = self.span {
+            println!(
+                "Splitting {} across {} monitors",
+                entry.path,
+                self.monitors.len()
+            );
+            span::split(Path::new(&entry.path), layout)?;
+        }
+        for monitor in &self.monitors {
+            set_system_wallpaper(&self.entry_for(entry, &monitor.name), config, &monitor.name)?;
+        }
+        Ok(())
+    }
+    /// Records a kept wallpaper in the history of each target monitor.
+    fn keep(&self, entry: &state::WallpaperEntry, originals: &[Original], config: &config::Config) {
+        for (monitor, original) in originals {
+            if self.monitors.iter().any(|m| &m.name == monitor) {
+                let entry = self.entry_for(entry, monitor);
+                record_history(&entry, original.as_ref(), config, monitor);
             }
-            _ => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                break;
+        }
+        update_lock_images(config);
+    }
+    /// Pins a previewed wallpaper to the workspace each target monitor is on.
+    /// The monitors keep their own wallpaper for every other workspace.
+    fn pin(
+        &self,
+        entry: &state::WallpaperEntry,
+        originals: &[Original],
+        config: &config::Config,
+    ) -> Result<()> {
+        let state = state::update_state(|state| {
+            for monitor in &self.monitors {
+                let workspace = &monitor.active_workspace.name;
+                if workspace.is_empty() {
+                    eprintln!("Warning: No active workspace known on {}", monitor.name);
+                    continue;
+                }
+                println!("Pinned to workspace {} on {}", workspace, monitor.name);
+                state
+                    .workspaces
+                    .insert(workspace.clone(), self.entry_for(entry, &monitor.name));
+                // The preview replaced the monitor's wallpaper in state, put it back
+                let original = originals.iter().find(|(name, _)| *name == monitor.name);
+                if let Some((_, Some(original))) = original {
+                    state
+                        .wallpapers
+                        .insert(monitor.name.clone(), original.clone());
+                }
             }
+            state.clone()
+        })?;
+        update_generated_configs(&state, config);
+        update_lock_images(config);
+        Ok(())
+    }
+    fn current_wallpapers(&self, config: &config::Config) -> Vec<Original> {
+        self.monitors
+            .iter()
+            .map(|m| (m.name.clone(), own_wallpaper_entry(config, &m.name)))
+            .collect()
+    }
+}
+/// The focused monitor, or a 1920x1080 stand-in if Hyprland can't be asked.
+fn focused_monitor() -> hyprland::Monitor {
+    hyprland::get_active_monitor().unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: Could not get active monitor: {}. Defaulting to config.",
+            e
+        );
+        hyprland::Monitor {
+            name: "".to_string(),
+            width: 1920,
+            height: 1080,
+            x: 0,
+            y: 0,
+            scale: 1.0,
+            focused: true,
+            transform: 0,
+            active_workspace: hyprland::ActiveWorkspace {
+                id: 1,
+                name: "1".to_string(),
+            },
+        }
+    })
+}
+/// Search ratio for a monitor: the per-monitor `ratios` override if set,
+/// otherwise landscape/portrait based on its orientation.
+fn monitor_ratio(config: &config::Config, monitor: &hyprland::Monitor) -> String {
+    if let Some(ratios) = config.monitor_ratios(&monitor.name) {
+        return ratios.to_string();
+    }
+    let (width, height) = monitor.get_visual_dimensions();
+    if width >= height {
+        "landscape".to_string()
+    } else {
+        "portrait".to_string()
+    }
+}
+fn set_specific_wallpaper(id: &str, config: &config::Config, targets: &Targets) -> Result<()> {
+    let wallpaper = get_wallpaper_info(id, config)?;
+    // Get active monitor info
+    let monitor = targets.primary();
+    let originals = targets.current_wallpapers(config);
+    let session = session::PreviewSession::begin(config, monitor, originals.clone())?;
+    println!("Downloading {}...", wallpaper.id);
+    let entry = download_to_library(&wallpaper, config)?;
+    targets.apply(&entry, config)?;
+    // Preview
+    use ui::NavAction;
+    match ui::show_preview_menu()? {
+        NavAction::Done => {
+            session.keep()?;
+            targets.keep(&entry, &originals, config);
+            std::process::exit(0);
+        }
+        NavAction::Cancel => {
+            session.cancel()?;
+        }
+        NavAction::OpenInBrowser => {
+            session.keep()?;
+            println!("Opening in browser: {}", wallpaper.short_url);
+            open::that(&wallpaper.short_url)?;
+            targets.keep(&entry, &originals, config);
+            std::process::exit(0);
+        }
+        _ => {
+            session.keep()?;
+            targets.keep(&entry, &originals, config);
         }
     }
     Ok(())
 }
-fn set_system_wallpaper(path: &PathBuf, config: &config::Config, monitor_name: &str) -> Result<()> {
-    let path_str = path.to_string_lossy();
-    let mut cmd_str = config.wallpaper_cmd.replace("%f", &path_str);
-    // Replace monitor placeholder
-    cmd_str = cmd_str.replace("%m", monitor_name);
-    // Also replace literal "monitor_name" just in case user has old config or literal instruction
-    cmd_str = cmd_str.replace("monitor_name", monitor_name);
-
-    // Apply wallpaper mode for hyprpaper
-    if (config.wallpaper_mode == "contain" || config.wallpaper_mode == "tile")
-        && cmd_str.contains("hyprpaper wallpaper")
-    {
-        cmd_str = format!(
-            "{},{}",
-            cmd_str.trim_end_matches('"'),
-            &format!("{}\"", config.wallpaper_mode)
-        );
+/// Downloads a Wallhaven wallpaper into the library (see `filename_template`)
+/// and returns the state entry for it.
+fn download_to_library(
+    wallpaper: &wallhaven::Wallpaper,
+    config: &config::Config,
+) -> Result<state::WallpaperEntry> {
+    let save_path = wallhaven::library_path(config, wallpaper);
+    download_wallpaper(&wallpaper.path, &save_path)?;
+    Ok(state::WallpaperEntry::from_wallhaven(&save_path, wallpaper))
+}
+/// The wallpaper currently shown on a monitor, with the metadata from state if it matches.
+/// Backends that can't report it fall back to state.
+fn current_wallpaper_entry(
+    config: &config::Config,
+    monitor_name: &str,
+) -> Option<state::WallpaperEntry> {
+    let saved = state::load_state()
+        .ok()
+        .and_then(|s| s.wallpapers.get(monitor_name).cloned());
+    let backend = backend::from_config(&config.for_monitor(monitor_name));
+    match backend.current(monitor_name) {
+        Ok(path) => match saved {
+            Some(entry) if entry.path == path => Some(entry),
+            _ => Some(state::WallpaperEntry::new(Path::new(&path))),
+        },
+        Err(_) => saved,
     }
+}
+/// The monitor's own wallpaper, the one a cancelled flow puts back. That is the
+/// one saved in state, not what the monitor shows, which may be pinned to the
+/// workspace it is on. Monitors without a saved wallpaper fall back to what the
+/// backend shows, unless that is a pinned one.
+fn own_wallpaper_entry(
+    config: &config::Config,
+    monitor_name: &str,
+) -> Option<state::WallpaperEntry> {
+    let state = state::load_state().unwrap_or_default();
+    if let Some(entry) = state.wallpapers.get(monitor_name) {
+        return Some(entry.clone());
+    }
+    current_wallpaper_entry(config, monitor_name)
+        .filter(|current| !state.workspaces.values().any(|e| e.path == current.path))
+}
+/// Puts a monitor's own wallpaper back after a cancelled preview. If the
+/// workspace it shows has a pinned wallpaper, that one is shown instead and
+/// `original` is only saved as the monitor's wallpaper.
+fn restore_own_wallpaper(
+    original: &state::WallpaperEntry,
+    config: &config::Config,
+    monitor_name: &str,
+) -> Result<()> {
+    let workspace = hyprland::get_monitors()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|m| m.name == monitor_name)
+        .map(|m| m.active_workspace.name);
+    let pinned = workspace.and_then(|workspace| {
+        state::load_state()
+            .ok()?
+            .workspaces
+            .remove(&workspace)
+            .filter(|entry| Path::new(&entry.path).exists())
+    });
+    let Some(pinned) = pinned else {
+        println!("Restoring original wallpaper: {}", original.path);
+        return set_system_wallpaper(original, config, monitor_name);
+    };
 
-    println!("Executing: {}", cmd_str);
+    println!("Restoring pinned wallpaper: {}", pinned.path);
+    show_wallpaper(&pinned, config, monitor_name)?;
+    let state = state::update_state(|state| {
+        state
+            .wallpapers
+            .insert(monitor_name.to_string(), original.clone());
+        state.clone()
+    })?;
+    update_generated_configs(&state, config);
+    Ok(())
+}
+fn set_system_wallpaper(
+    entry: &state::WallpaperEntry,
+    config: &config::Config,
+    monitor_name: &str,
+) -> Result<()> {
+    show_wallpaper(entry, config, monitor_name)?;
 
-    // Execute command
-    for sub_cmd in cmd_str.split(';') {
-        let trimmed = sub_cmd.trim();
-        if trimmed.is_empty() {
-            continue;
+    // Save State
+    let config = &config.for_monitor(monitor_name);
+    let mut entry = entry.clone();
+    entry.mode = Some(config.wallpaper_mode.clone());
+    let state = match state::update_state(|state| {
+        state.wallpapers.insert(monitor_name.to_string(), entry);
+        state.clone()
+    }) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Warning: Failed to save state: {}", e);
+            return Ok(());
+        }
+    };
+
+    update_generated_configs(&state, config);
+    Ok(())
+}
+
+/// Rewrites the config files (and links) generated from the saved wallpapers.
+/// Runs after every change to them.
+fn update_generated_configs(state: &state::State, config: &config::Config) {
+    if let Err(e) = hyprlock::update_hyprlock_config(state, config) {
+        eprintln!("Warning: Failed to update hyprlock config: {}", e);
+    }
+    if let Err(e) = hyprpaper::update_hyprpaper_config(state) {
+        eprintln!("Warning: Failed to update hyprpaper config: {}", e);
+    }
+    if config.swaylock {
+        if let Err(e) = lockers::update_swaylock_config(state, config) {
+            eprintln!("Warning: Failed to update swaylock config: {}", e);
+        }
+    }
+    if config.gtklock {
+        if let Err(e) = lockers::update_gtklock_config(state, config) {
+            eprintln!("Warning: Failed to update gtklock config: {}", e);
         }
-        let status = Command::new("sh").arg("-c").arg(trimmed).spawn()?.wait()?;
-        if !status.success() {
-            return Err(anyhow::anyhow!("Command failed: {}", trimmed));
+    }
+    if let Some(link) = &config.greeter_wallpaper {
+        if let Err(e) = lockers::update_greeter_link(state, config, link) {
+            eprintln!("Warning: Failed to update greeter wallpaper: {}", e);
         }
     }
+}
 
-    // Save State
-    let mut state = state::load_state().unwrap_or_default();
-    state
-        .wallpapers
-        .insert(monitor_name.to_string(), path_str.to_string());
-    if let Err(e) = state::save_state(&state) {
-        eprintln!("Warning: Failed to save state: {}", e);
+/// Creates the lock screen copies (`lock_image`) of the saved wallpapers and
+/// points the generated configs at them. Blurring a full-size image takes a
+/// moment, so this runs once a wallpaper is kept, not on every preview step.
+fn update_lock_images(config: &config::Config) {
+    if !config.lock_image {
+        return;
+    }
+    let state = match state::load_state() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Warning: Failed to load state: {}", e);
+            return;
+        }
+    };
+    for entry in state.wallpapers.values() {
+        if let Err(e) = lockscreen::processed_copy(Path::new(&entry.path), config) {
+            eprintln!("Warning: Failed to create lock screen image: {}", e);
+        }
+    }
+    if let Err(e) = lockscreen::remove_unused_copies(&state, config) {
+        eprintln!("Warning: Failed to remove old lock screen images: {}", e);
     }
+    update_generated_configs(&state, config);
+}
 
-    // Update Hyprlock
-    if let Err(e) = hyprlock::update_hyprlock_config(&state) {
-        eprintln!("Warning: Failed to update hyprlock config: {}", e);
+/// Applies a wallpaper through the configured backend without recording it
+/// in the state, e.g. for wallpapers pinned to a workspace.
+fn show_wallpaper(
+    entry: &state::WallpaperEntry,
+    config: &config::Config,
+    monitor_name: &str,
+) -> Result<()> {
+    let config = &config.for_monitor(monitor_name);
+    let backend = backend::from_config(config);
+    backend.preload(&entry.path)?;
+    backend.apply(monitor_name, entry, &config.wallpaper_mode)?;
+    // Previews would otherwise pile up in memory
+    if let Err(e) = backend.unload_unused() {
+        eprintln!("Warning: Failed to unload unused wallpapers: {}", e);
     }
+    Ok(())
+}
 
+/// Adds a kept wallpaper to the monitor's history. An empty history is seeded
+/// with the wallpaper it replaced, so the first undo has somewhere to go.
+fn record_history(
+    entry: &state::WallpaperEntry,
+    replaced: Option<&state::WallpaperEntry>,
+    config: &config::Config,
+    monitor_name: &str,
+) {
+    let result = state::update_state(|state| {
+        let history = state.history.entry(monitor_name.to_string()).or_default();
+        if history.entries.is_empty() {
+            if let Some(replaced) = replaced {
+                history.push(replaced.clone(), config.history_size);
+            }
+        }
+        history.push(entry.clone(), config.history_size);
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: Failed to save history: {}", e);
+    }
+}
+/// Undo (or redo, if `forward`) on each target monitor's history.
+fn step_history(config: &config::Config, targets: &Targets, forward: bool) -> Result<()> {
+    for monitor in &targets.monitors {
+        step_monitor_history(config, &monitor.name, forward)?;
+    }
+    update_lock_images(config);
+    Ok(())
+}
+fn step_monitor_history(config: &config::Config, monitor_name: &str, forward: bool) -> Result<()> {
+    // Step under the lock, so a concurrent change to the history isn't overwritten
+    let step = state::update_state(|state| {
+        let history = state.history.get_mut(monitor_name)?;
+        let previous = history.position;
+        let target = if forward {
+            history.redo()
+        } else {
+            history.undo()
+        };
+        target
+            .cloned()
+            .map(|target| (target, previous, history.position))
+    })?;
+    let Some((mut target, previous, position)) = step else {
+        println!(
+            "Nothing to {} on {}",
+            if forward { "redo" } else { "undo" },
+            monitor_name
+        );
+        return Ok(());
+    };
+    target.set_at = Some(state::now());
+    println!("Setting {} on {}", target.path, monitor_name);
+    if let Err(e) = set_system_wallpaper(&target, config, monitor_name) {
+        // Step back unless the history moved on in the meantime
+        state::update_state(|state| {
+            if let Some(history) = state.history.get_mut(monitor_name) {
+                if history.position == position {
+                    history.position = previous;
+                }
+            }
+        })?;
+        return Err(e);
+    }
+    Ok(())
+}
+/// Lists the first target monitor's history and re-applies the selected
+/// wallpaper. The other target monitors go back to the wallpaper they were given
+/// together with it (e.g. their piece of a spanned image), if they have one.
+fn handle_history(config: &config::Config, targets: &Targets) -> Result<()> {
+    let monitor_name = targets.primary().name.clone();
+    let state = state::load_state()?;
+    let history = state
+        .history
+        .get(&monitor_name)
+        .cloned()
+        .unwrap_or_default();
+    if history.entries.is_empty() {
+        println!("No history for {}", monitor_name);
+        return Ok(());
+    }
+
+    // Newest first
+    let now = state::now();
+    let entries: Vec<(usize, &state::WallpaperEntry)> =
+        history.entries.iter().enumerate().rev().collect();
+    let items: Vec<String> = entries
+        .iter()
+        .map(|(i, entry)| {
+            let name = entry.display_name();
+            let age = entry
+                .set_at
+                .map(|t| format!(" ({})", ui::format_age(t, now)))
+                .unwrap_or_default();
+            let marker = if *i == history.position { "▶ " } else { "" };
+            format!("{}{}. {}{}", marker, i + 1, name, age)
+        })
+        .collect();
+
+    if let Some(index) = ui::show_history_menu(&items)? {
+        let selected = entries[index].1;
+        for monitor in &targets.monitors {
+            let entry =
+                if monitor.name == monitor_name {
+                    Some(selected)
+                } else {
+                    state.history.get(&monitor.name).and_then(|history| {
+                        history.entries.iter().rev().find(|entry| {
+                            selected.set_at.is_some() && entry.set_at == selected.set_at
+                        })
+                    })
+                };
+            let Some(entry) = entry else {
+                println!(
+                    "Nothing set together with it on {}, leaving it",
+                    monitor.name
+                );
+                continue;
+            };
+            let mut entry = entry.clone();
+            entry.set_at = Some(now);
+            set_system_wallpaper(&entry, config, &monitor.name)?;
+            record_history(&entry, None, config, &monitor.name);
+        }
+        update_lock_images(config);
+    }
     Ok(())
 }
 
+fn print_status() -> Result<()> {
+    let state = state::load_state()?;
+    if state.wallpapers.is_empty() {
+        println!("No wallpapers set");
+        return Ok(());
+    }
+    let now = state::now();
+    let mut monitors: Vec<_> = state.wallpapers.iter().collect();
+    monitors.sort_by_key(|(name, _)| name.as_str());
+    for (monitor, entry) in monitors {
+        println!("{}:", monitor);
+        println!("  path: {}", entry.path);
+        if let Some(id) = &entry.wallhaven_id {
+            println!("  wallhaven id: {}", id);
+        }
+        if let Some(url) = &entry.source_url {
+            println!("  source: {}", url);
+        }
+        if let Some(resolution) = &entry.resolution {
+            println!("  resolution: {}", resolution);
+        }
+        if let Some(purity) = &entry.purity {
+            println!("  purity: {}", purity);
+        }
+        if !entry.tags.is_empty() {
+            println!("  tags: {}", entry.tags.join(", "));
+        }
+        if let Some(mode) = &entry.mode {
+            println!("  mode: {}", mode);
+        }
+        if let Some(set_at) = entry.set_at {
+            println!("  set: {}", ui::format_age(set_at, now));
+        }
+    }
+    let mut pinned: Vec<_> = state.workspaces.iter().collect();
+    pinned.sort_by_key(|(workspace, _)| workspace.as_str());
+    for (workspace, entry) in pinned {
+        println!("workspace {}: {}", workspace, entry.path);
+    }
+    Ok(())
+}
+
+/// Re-applies the saved wallpapers in one batch once Hyprland and the wallpaper
+/// backend are up, waiting at most `restore_timeout` seconds for them.
+/// Monitors that aren't connected are skipped (the daemon picks them up later);
+/// fails if any connected monitor couldn't be restored.
 fn restore_wallpapers(config: &config::Config) -> Result<()> {
     let state = state::load_state()?;
+    if state.wallpapers.is_empty() {
+        println!("No wallpapers to restore");
+        return Ok(());
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(config.restore_timeout);
+    let connected = wait_until(deadline, "Hyprland", || {
+        let monitors = hyprland::get_monitors()?;
+        if monitors.is_empty() {
+            anyhow::bail!("No monitors connected yet");
+        }
+        Ok(monitors)
+    })?;
+    let backend = backend::from_config(config);
+    wait_until(deadline, &config.backend, || backend.ready())?;
+
     println!("Restoring wallpapers from state...");
-    for (monitor, path) in &state.wallpapers {
-        let path_buf = PathBuf::from(path);
-        if path_buf.exists() {
-            println!("Restoring {} on {}", path, monitor);
-            // We reuse set_system_wallpaper but we must be careful not to create a loop
-            // set_system_wallpaper saves state again. That's fine, it's idempotent.
+    let mut saved: Vec<_> = state.wallpapers.iter().collect();
+    saved.sort_by_key(|(monitor, _)| monitor.as_str());
+    let mut restored = 0;
+    let mut skipped = 0;
+    let mut failed = Vec::new();
+    for (monitor, entry) in saved {
+        if !connected.iter().any(|m| &m.name == monitor) {
+            println!("Skipping {}: not connected", monitor);
+            skipped += 1;
+            continue;
+        }
+        if !Path::new(&entry.path).exists() {
+            eprintln!("Wallpaper not found: {}", entry.path);
+            failed.push(monitor.as_str());
+            continue;
+        }
+        println!("Restoring {} on {}", entry.path, monitor);
+        // The state already has it, only the backend needs to be told
+        match show_wallpaper(entry, config, monitor) {
+            Ok(()) => restored += 1,
+            Err(e) => {
+                eprintln!("Failed to restore wallpaper on {}: {}", monitor, e);
+                failed.push(monitor.as_str());
+            }
+        }
+    }
+    update_generated_configs(&state, config);
+    update_lock_images(config);
 
-            let mut attempts = 0;
-            const MAX_ATTEMPTS: i32 = 5;
-            loop {
-                match set_system_wallpaper(&path_buf, config, monitor) {
-                    Ok(_) => break,
-                    Err(e) => {
-                        attempts += 1;
-                        if attempts >= MAX_ATTEMPTS {
-                            eprintln!(
-                                "Failed to restore wallpaper on {}: {} (after {} attempts)",
-                                monitor, e, attempts
-                            );
-                            break;
-                        }
-                        eprintln!(
-                            "Attempt {}/{} failed for {}: {}. Retrying in 1s...",
-                            attempts, MAX_ATTEMPTS, monitor, e
-                        );
-                        thread::sleep(Duration::from_secs(1));
-                    }
+    println!(
+        "Restored {} monitor(s), {} not connected, {} failed",
+        restored,
+        skipped,
+        failed.len()
+    );
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to restore wallpapers on {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// Calls `probe` until it succeeds or `deadline` passes, returning its last error then.
+fn wait_until<T>(deadline: Instant, what: &str, mut probe: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut waiting = false;
+    loop {
+        match probe() {
+            Ok(value) => return Ok(value),
+            Err(e) if Instant::now() >= deadline => {
+                return Err(e.context(format!("Timed out waiting for {}", what)));
+            }
+            Err(_) => {
+                if !waiting {
+                    println!("Waiting for {}...", what);
+                    waiting = true;
                 }
+                thread::sleep(Duration::from_millis(250));
             }
-        } else {
-            eprintln!("Wallpaper not found: {}", path);
         }
     }
-    Ok(())
 }
 
-fn handle_menu(config: &mut config::Config) -> Result<()> {
+/// `set_system_wallpaper`, retried while the wallpaper backend (or a freshly
+/// connected monitor) isn't ready yet.
+fn set_wallpaper_with_retry(
+    entry: &state::WallpaperEntry,
+    config: &config::Config,
+    monitor: &str,
+) -> Result<()> {
+    with_retry(monitor, || set_system_wallpaper(entry, config, monitor))
+}
+
+/// Retries applying a wallpaper to `monitor` a few times, one second apart.
+fn with_retry(monitor: &str, mut apply: impl FnMut() -> Result<()>) -> Result<()> {
+    let mut attempts = 0;
+    const MAX_ATTEMPTS: i32 = 5;
     loop {
-        let mut current_wallhaven_id = None;
-        if let Ok(monitor) = hyprland::get_active_monitor() {
-            if let Ok(path) = hyprland::get_current_wallpaper(&monitor.name) {
-                let path_obj = std::path::Path::new(&path);
-                if let Some(filename) = path_obj.file_name().and_then(|f| f.to_str()) {
-                    if let Some(rest) = filename.strip_prefix("wallhaven-") {
-                        if let Some(dot_idx) = rest.rfind('.') {
-                            current_wallhaven_id = Some(rest[..dot_idx].to_string());
-                        }
-                    }
+        match apply() {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                attempts += 1;
+                if attempts >= MAX_ATTEMPTS {
+                    anyhow::bail!("{} (after {} attempts)", e, attempts);
                 }
+                eprintln!(
+                    "Attempt {}/{} failed for {}: {}. Retrying in 1s...",
+                    attempts, MAX_ATTEMPTS, monitor, e
+                );
+                thread::sleep(Duration::from_secs(1));
             }
         }
+    }
+}
+
+fn handle_menu(config: &mut config::Config, targets: &Targets) -> Result<()> {
+    loop {
+        let current_source_url = current_wallpaper_entry(config, &targets.primary().name)
+            .and_then(|entry| entry.source_url);
         use ui::MenuAction;
-        match ui::show_fuzzel_menu(current_wallhaven_id.is_some())? {
+        match ui::show_fuzzel_menu(current_source_url.is_some())? {
             MenuAction::Rotate => {
-                rotate_wallpaper(config)?;
+                rotate_wallpaper(config, targets)?;
             }
             MenuAction::Collections => {
-                handle_collections(config)?;
+                handle_collections(config, targets)?;
+            }
+            MenuAction::History => {
+                handle_history(config, targets)?;
             }
             MenuAction::OpenCurrent => {
-                if let Some(id) = current_wallhaven_id {
-                    let url = format!("https://wallhaven.cc/w/{}", id);
+                if let Some(url) = current_source_url {
                     open::that(url)?;
                     return Ok(());
                 }
             }
             MenuAction::SearchApi => {
-                search_interactive(config, None)?;
+                search_interactive(config, None, targets)?;
                 // If search_interactive returns Ok(()), it means either Done or cancelled from query.
                 // In either case, we want to stay in the main menu loop, not exit the app.
             }
@@ -450,7 +1192,7 @@ fn handle_menu(config: &mut config::Config) -> Result<()> {
                     } else {
                         input
                     };
-                    set_specific_wallpaper(&id, config)?;
+                    set_specific_wallpaper(&id, config, targets)?;
                 }
             }
             MenuAction::Settings => {
@@ -463,7 +1205,7 @@ fn handle_menu(config: &mut config::Config) -> Result<()> {
                 // Check 1: Wallhaven URL or ID
                 if input.contains("wallhaven.cc/w/") {
                     let id = input.split("/w/").last().unwrap_or(&input).to_string();
-                    set_specific_wallpaper(&id, config)?;
+                    set_specific_wallpaper(&id, config, targets)?;
                     continue;
                 }
                 // Simple alphanumeric check for potential ID (length 6)
@@ -489,7 +1231,7 @@ fn handle_menu(config: &mut config::Config) -> Result<()> {
                     // Let's try to fetch it.
                     match get_wallpaper_info(&input, config) {
                         Ok(_) => {
-                            set_specific_wallpaper(&input, config)?;
+                            set_specific_wallpaper(&input, config, targets)?;
                             continue;
                         }
                         Err(_) => {
@@ -505,11 +1247,11 @@ fn handle_menu(config: &mut config::Config) -> Result<()> {
                         || lower_input.ends_with(".png")
                         || lower_input.ends_with(".webp"))
                 {
-                    set_direct_wallpaper(&input, config)?;
+                    set_direct_wallpaper(&input, config, targets)?;
                     continue;
                 }
                 // Check 3: Fallback Search
-                search_interactive(config, Some(input))?;
+                search_interactive(config, Some(input), targets)?;
                 // If search_interactive returns Ok(()), it means either Done or cancelled from query.
                 // In either case, we want to stay in the main menu loop, not exit the app.
             }
@@ -529,23 +1271,17 @@ fn handle_settings(config: &mut config::Config) -> Result<()> {
             &config.wallpaper_mode,
         )? {
             SettingsAction::Categories => {
-                loop {
-                    if let Some(new_cats) = ui::show_categories_menu(&config.categories)? {
-                        config.categories = new_cats;
-                        config::save_config(config)?;
-                    } else {
-                        break; // Back to settings menu
-                    }
+                // Loops until Back, which returns to the settings menu
+                while let Some(new_cats) = ui::show_categories_menu(&config.categories)? {
+                    config.categories = new_cats;
+                    config::save_config(config)?;
                 }
             }
             SettingsAction::Purity => {
-                loop {
-                    if let Some(new_purity) = ui::show_purity_menu(&config.purity)? {
-                        config.purity = new_purity;
-                        config::save_config(config)?;
-                    } else {
-                        break; // Back to settings menu
-                    }
+                // Loops until Back, which returns to the settings menu
+                while let Some(new_purity) = ui::show_purity_menu(&config.purity)? {
+                    config.purity = new_purity;
+                    config::save_config(config)?;
                 }
             }
             SettingsAction::Sorting => {
@@ -555,7 +1291,9 @@ fn handle_settings(config: &mut config::Config) -> Result<()> {
                 }
             }
             SettingsAction::WallpaperMode => {
-                if let Some(new_mode) = ui::show_wallpaper_mode_menu(&config.wallpaper_mode)? {
+                let modes = backend::from_config(config).modes();
+                if let Some(new_mode) = ui::show_wallpaper_mode_menu(&config.wallpaper_mode, modes)?
+                {
                     config.wallpaper_mode = new_mode;
                     config::save_config(config)?;
                 }
@@ -563,7 +1301,13 @@ fn handle_settings(config: &mut config::Config) -> Result<()> {
             SettingsAction::SetApiKey => {
                 if let Ok(key) = ui::get_password_input("Enter Wallhaven API Key:") {
                     if !key.is_empty() {
-                        config.api_key = Some(key);
+                        if config.api_key_secret_service {
+                            // Keep the key out of the config file
+                            secrets::store_secret_service(&key)?;
+                            config.set_api_key(None);
+                        } else {
+                            config.set_api_key(Some(key));
+                        }
                         config::save_config(config)?;
                     }
                 }
@@ -607,7 +1351,7 @@ fn ensure_username(config: &mut config::Config) -> Result<String> {
     }
 }
 
-fn handle_collections(config: &mut config::Config) -> Result<()> {
+fn handle_collections(config: &mut config::Config, targets: &Targets) -> Result<()> {
     let username = match ensure_username(config) {
         Ok(u) => u,
         Err(e) => {
@@ -635,7 +1379,13 @@ fn handle_collections(config: &mut config::Config) -> Result<()> {
 
     if let Some(label) = selection {
         if let Some(collection) = collections.iter().find(|c| c.label == label) {
-            view_collection_wallpapers(config, &username, collection.id, &collection.label)?;
+            view_collection_wallpapers(
+                config,
+                &username,
+                collection.id,
+                &collection.label,
+                targets,
+            )?;
         }
     }
 
@@ -647,18 +1397,11 @@ fn view_collection_wallpapers(
     username: &str,
     collection_id: i64,
     collection_label: &str,
+    targets: &Targets,
 ) -> Result<()> {
     // 1. Get Monitor
-    let monitor = hyprland::get_active_monitor().unwrap_or_else(|_| hyprland::Monitor {
-        name: "".to_string(),
-        width: 1920,
-        height: 1080,
-        focused: true,
-        transform: 0,
-        active_workspace: hyprland::ActiveWorkspace { id: 1 },
-    });
-    let original_wallpaper = hyprland::get_current_wallpaper(&monitor.name).ok();
-    let original_workspace_id = monitor.active_workspace.id;
+    let monitor = targets.primary();
+    let originals = targets.current_wallpapers(config);
 
     println!("Viewing collection: {}", collection_label);
 
@@ -672,18 +1415,12 @@ fn view_collection_wallpapers(
         return Ok(());
     }
 
-    // 3. Setup Workspace
-    let occupied = hyprland::get_occupied_workspaces().unwrap_or_default();
-    let mut empty_workspace_id = 10;
-    while occupied.contains(&empty_workspace_id) {
-        empty_workspace_id += 1;
-    }
-    hyprland::dispatch_workspace(empty_workspace_id)?;
+    let session = session::PreviewSession::begin(config, monitor, originals.clone())?;
 
     // 4. Interactive Loop
     let mut index = 0;
     let mut total = wallpapers.len();
-    let mut _current_set_path = None; // Keep track to avoid excessive downloads? Not used logic in search_interactive either really.
+    let mut current_entry = None; // Wallpaper being previewed, recorded in history when kept
 
     // Set first immediately
     {
@@ -699,12 +1436,9 @@ fn view_collection_wallpapers(
         // Let's do the same for consistency.
         match get_wallpaper_info(&chosen_summary.id, config) {
             Ok(chosen) => {
-                let ext = chosen.path.split('.').last().unwrap_or("jpg");
-                let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-                let save_path = expand_path(&config.save_dir).join(&filename);
-                download_wallpaper(&chosen.path, &save_path)?;
-                set_system_wallpaper(&save_path, config, &monitor.name)?;
-                _current_set_path = Some(save_path);
+                let entry = download_to_library(&chosen, config)?;
+                targets.apply(&entry, config)?;
+                current_entry = Some(entry);
             }
             Err(e) => eprintln!("Failed to load wallpaper info: {}", e),
         }
@@ -723,7 +1457,7 @@ fn view_collection_wallpapers(
         match ui::show_search_nav_menu(
             index,
             total,
-            config.api_key.is_some(),
+            config.has_api_key(),
             "N/A", // user can't change category of a collection view usually
             "N/A",
             "N/A",
@@ -760,22 +1494,31 @@ fn view_collection_wallpapers(
                 index = rand::thread_rng().gen_range(0..total);
             }
             NavAction::OpenInBrowser => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
+                session.keep()?;
                 let chosen_summary = &wallpapers[index];
                 println!("Opening in browser: {}", chosen_summary.short_url);
                 open::that(&chosen_summary.short_url)?;
+                if let Some(ref entry) = current_entry {
+                    targets.keep(entry, &originals, config);
+                }
                 std::process::exit(0);
             }
             NavAction::Done => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
+                session.keep()?;
+                if let Some(ref entry) = current_entry {
+                    targets.keep(entry, &originals, config);
+                }
                 std::process::exit(0);
             }
-            NavAction::Cancel | NavAction::None => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                if let Some(ref orig_path) = original_wallpaper {
-                    println!("Restoring original wallpaper: {}", orig_path);
-                    set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
+            NavAction::PinToWorkspace => {
+                session.keep()?;
+                if let Some(ref entry) = current_entry {
+                    targets.pin(entry, &originals, config)?;
                 }
+                std::process::exit(0);
+            }
+            NavAction::Cancel | NavAction::None => {
+                session.cancel()?;
                 break 'nav_loop;
             }
             // Ignore settings actions for collections
@@ -785,18 +1528,13 @@ fn view_collection_wallpapers(
         // Apply new selection
         let chosen_summary = &wallpapers[index];
         match get_wallpaper_info(&chosen_summary.id, config) {
-            Ok(chosen) => {
-                let ext = chosen.path.split('.').last().unwrap_or("jpg");
-                let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-                let save_path = expand_path(&config.save_dir).join(&filename);
-                match download_wallpaper(&chosen.path, &save_path) {
-                    Ok(_) => {
-                        set_system_wallpaper(&save_path, config, &monitor.name)?;
-                        _current_set_path = Some(save_path);
-                    }
-                    Err(e) => eprintln!("Failed to download wallpaper: {}", e),
+            Ok(chosen) => match download_to_library(&chosen, config) {
+                Ok(entry) => {
+                    targets.apply(&entry, config)?;
+                    current_entry = Some(entry);
                 }
-            }
+                Err(e) => eprintln!("Failed to download wallpaper: {}", e),
+            },
             Err(e) => eprintln!("Failed to load wallpaper info: {}", e),
         }
     }
@@ -807,27 +1545,13 @@ fn view_collection_wallpapers(
 fn search_interactive(
     global_config: &mut config::Config,
     mut initial_query: Option<String>,
+    targets: &Targets,
 ) -> Result<()> {
-    let mut config_val = global_config.clone();
-    let config = &mut config_val;
-
     // 1. Get Monitor & Original State
-    let monitor = hyprland::get_active_monitor().unwrap_or_else(|e| {
-        eprintln!(
-            "Warning: Could not get active monitor: {}. Defaulting to config.",
-            e
-        );
-        hyprland::Monitor {
-            name: "".to_string(),
-            width: 1920,
-            height: 1080,
-            focused: true,
-            transform: 0,
-            active_workspace: hyprland::ActiveWorkspace { id: 1 },
-        }
-    });
-    let original_wallpaper = hyprland::get_current_wallpaper(&monitor.name).ok(); // It's okay if we fail to get it
-    let original_workspace_id = monitor.active_workspace.id;
+    let monitor = targets.primary();
+    let mut config_val = targets.search_config(global_config);
+    let config = &mut config_val;
+    let originals = targets.current_wallpapers(global_config);
     'query_input_loop: loop {
         // 2. Prompt Query (if not provided)
         let query = match initial_query.take() {
@@ -843,12 +1567,8 @@ fn search_interactive(
             }
         };
         // 3. Fetch Results
-        let (width, height) = monitor.get_visual_dimensions();
-        let ratio = if width >= height {
-            "landscape"
-        } else {
-            "portrait"
-        };
+        let ratio = targets.ratio(config);
+        let ratio = ratio.as_str();
         println!("Searching '{}' for {} ({})", query, monitor.name, ratio);
         let mut current_page = 1;
         let mut wallpapers = search_wallpapers(config, Some(&query), current_page, Some(ratio))?;
@@ -856,36 +1576,26 @@ fn search_interactive(
             eprintln!("No results found for '{}'.", query);
             continue 'query_input_loop; // Go back to query prompt
         }
-        // Find empty workspace
-        let occupied = hyprland::get_occupied_workspaces().unwrap_or_default();
-        let mut empty_workspace_id = 10; // Start checking from 10 to preserve early workspaces
-        while occupied.contains(&empty_workspace_id) {
-            empty_workspace_id += 1;
-        }
-        // Switch to empty workspace
-        hyprland::dispatch_workspace(empty_workspace_id)?;
+        let session = session::PreviewSession::begin(global_config, monitor, originals.clone())?;
         // 4. Interactive Loop
         let mut index = 0;
         let mut total = wallpapers.len();
-        let mut _current_set_path = None;
+        let mut current_entry;
         // Set first immediately
         {
             let chosen_summary = &wallpapers[index];
             // Fetch full details to get authorized download URL
             let chosen = get_wallpaper_info(&chosen_summary.id, config)?;
-            let ext = chosen.path.split('.').last().unwrap_or("jpg");
-            let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-            let save_path = expand_path(&config.save_dir).join(&filename);
-            download_wallpaper(&chosen.path, &save_path)?;
-            set_system_wallpaper(&save_path, config, &monitor.name)?;
-            _current_set_path = Some(save_path);
+            let entry = download_to_library(&chosen, config)?;
+            targets.apply(&entry, global_config)?;
+            current_entry = Some(entry);
         }
         'nav_loop: loop {
             use ui::NavAction;
             match ui::show_search_nav_menu(
                 index,
                 total,
-                config.api_key.is_some(),
+                config.has_api_key(),
                 &config.categories,
                 &config.purity,
                 &config.sorting,
@@ -987,7 +1697,7 @@ fn search_interactive(
                 }
                 NavAction::OpenInBrowser => {
                     // Restore workspace
-                    hyprland::dispatch_workspace(original_workspace_id)?;
+                    session.keep()?;
                     // Construct the full search URL including all parameters
                     let search_url = format!(
                     "https://wallhaven.cc/search?q={}&categories={}&purity={}&sorting={}&ratios={}",
@@ -995,68 +1705,54 @@ fn search_interactive(
                 );
                     println!("Opening search results in browser: {}", search_url);
                     open::that(search_url)?;
+                    if let Some(ref entry) = current_entry {
+                        targets.keep(entry, &originals, global_config);
+                    }
                     std::process::exit(0);
                 }
                 NavAction::Done => {
                     // Restore workspace before exiting
-                    hyprland::dispatch_workspace(original_workspace_id)?;
+                    session.keep()?;
+                    if let Some(ref entry) = current_entry {
+                        targets.keep(entry, &originals, global_config);
+                    }
                     std::process::exit(0);
                 }
-                NavAction::Cancel => {
-                    // Restore workspace first (so we see the restoration happening on original workspace? or hidden?)
-                    // Probably restore workspace first so user is back in context.
-                    hyprland::dispatch_workspace(original_workspace_id)?;
-                    // Restore original and go back to query prompt
-                    if let Some(ref orig_path) = original_wallpaper {
-                        println!("Restoring original wallpaper: {}", orig_path);
-                        set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
+                NavAction::PinToWorkspace => {
+                    session.keep()?;
+                    if let Some(ref entry) = current_entry {
+                        targets.pin(entry, &originals, global_config)?;
                     }
+                    std::process::exit(0);
+                }
+                NavAction::Cancel => {
+                    // Restore workspace and original wallpaper, then go back to query prompt
+                    session.cancel()?;
                     break 'nav_loop; // Break inner loop, go to query_input_loop
                 }
                 NavAction::None => {
-                    hyprland::dispatch_workspace(original_workspace_id)?;
                     // User escaped menu without explicit selection. Treat as cancel.
-                    if let Some(ref orig_path) = original_wallpaper {
-                        println!("Restoring original wallpaper: {}", orig_path);
-                        set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
-                    }
+                    session.cancel()?;
                     break 'nav_loop; // Break inner loop, go to query_input_loop
                 }
             }
             // Apply new selection (if not done/cancel)
             let chosen_summary = &wallpapers[index];
             let chosen = get_wallpaper_info(&chosen_summary.id, config)?;
-            let ext = chosen.path.split('.').last().unwrap_or("jpg");
-            let filename = format!("wallhaven-{}.{}", chosen.id, ext);
-            let save_path = expand_path(&config.save_dir).join(&filename);
             // Only download if changed? (Always download for now, it checks existence inside)
-            download_wallpaper(&chosen.path, &save_path)?;
-            set_system_wallpaper(&save_path, config, &monitor.name)?;
-            _current_set_path = Some(save_path);
+            let entry = download_to_library(&chosen, config)?;
+            targets.apply(&entry, global_config)?;
+            current_entry = Some(entry);
         }
     }
 }
-fn set_direct_wallpaper(url: &str, config: &config::Config) -> Result<()> {
+fn set_direct_wallpaper(url: &str, config: &config::Config, targets: &Targets) -> Result<()> {
     // Try to get monitor
-    let monitor = hyprland::get_active_monitor().unwrap_or_else(|_| hyprland::Monitor {
-        name: "".to_string(),
-        width: 1920,
-        height: 1080,
-        focused: true,
-        transform: 0,
-        active_workspace: hyprland::ActiveWorkspace { id: 1 },
-    });
-    let original_wallpaper = hyprland::get_current_wallpaper(&monitor.name).ok();
-    let original_workspace_id = monitor.active_workspace.id;
-    // Switch to empty workspace
-    let occupied = hyprland::get_occupied_workspaces().unwrap_or_default();
-    let mut empty_workspace_id = 10;
-    while occupied.contains(&empty_workspace_id) {
-        empty_workspace_id += 1;
-    }
-    hyprland::dispatch_workspace(empty_workspace_id)?;
+    let monitor = targets.primary();
+    let originals = targets.current_wallpapers(config);
+    let session = session::PreviewSession::begin(config, monitor, originals.clone())?;
     // Derive filename
-    let filename = url.split('/').last().unwrap_or("wallpaper.jpg");
+    let filename = url.split('/').next_back().unwrap_or("wallpaper.jpg");
     let filename = if filename.is_empty() {
         "wallpaper.jpg"
     } else {
@@ -1065,33 +1761,29 @@ fn set_direct_wallpaper(url: &str, config: &config::Config) -> Result<()> {
     let save_path = expand_path(&config.save_dir).join(filename);
     println!("Downloading direct image to {:?}", save_path);
     download_wallpaper(url, &save_path)?;
-    set_system_wallpaper(&save_path, config, &monitor.name)?;
-    // Preview Loop
-    loop {
-        use ui::NavAction;
-        match ui::show_preview_menu()? {
-            NavAction::Done => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                std::process::exit(0);
-            }
-            NavAction::Cancel => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                if let Some(ref orig_path) = original_wallpaper {
-                    println!("Restoring original wallpaper: {}", orig_path);
-                    set_system_wallpaper(&PathBuf::from(orig_path), config, &monitor.name)?;
-                }
-                break;
-            }
-            NavAction::OpenInBrowser => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                println!("Opening in browser: {}", url);
-                open::that(url)?;
-                std::process::exit(0);
-            }
-            _ => {
-                hyprland::dispatch_workspace(original_workspace_id)?;
-                break;
-            }
+    let entry = state::WallpaperEntry::from_url(&save_path, url);
+    targets.apply(&entry, config)?;
+    // Preview
+    use ui::NavAction;
+    match ui::show_preview_menu()? {
+        NavAction::Done => {
+            session.keep()?;
+            targets.keep(&entry, &originals, config);
+            std::process::exit(0);
+        }
+        NavAction::Cancel => {
+            session.cancel()?;
+        }
+        NavAction::OpenInBrowser => {
+            session.keep()?;
+            println!("Opening in browser: {}", url);
+            open::that(url)?;
+            targets.keep(&entry, &originals, config);
+            std::process::exit(0);
+        }
+        _ => {
+            session.keep()?;
+            targets.keep(&entry, &originals, config);
         }
     }
     Ok(())
@@ -0,0 +1,89 @@
+use crate::config::{expand_path, Config};
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Attributes identifying the API key in the Secret Service
+const SECRET_ATTRIBUTES: [&str; 4] = ["application", "hyprwallhaven", "key", "api_key"];
+
+/// Resolves the API key from the configured sources, in order:
+/// `api_key`, `api_key_cmd`, `api_key_file`, then the Secret Service.
+pub fn resolve_api_key(config: &Config) -> Result<Option<String>> {
+    if let Some(key) = &config.api_key {
+        return Ok(Some(key.clone()));
+    }
+
+    if let Some(cmd) = &config.api_key_cmd {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run api_key_cmd: {}", cmd))?;
+        if !output.status.success() {
+            anyhow::bail!("api_key_cmd failed ({}): {}", output.status, cmd);
+        }
+        // Tools like `pass` print the secret on the first line
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Ok(non_empty(stdout.lines().next().unwrap_or_default()));
+    }
+
+    if let Some(file) = &config.api_key_file {
+        let path = expand_path(file);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read api_key_file {}", path.display()))?;
+        return Ok(non_empty(&content));
+    }
+
+    if config.api_key_secret_service {
+        return lookup_secret_service();
+    }
+
+    Ok(None)
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+/// Looks up the API key in the Secret Service via libsecret's `secret-tool`.
+pub fn lookup_secret_service() -> Result<Option<String>> {
+    let output = Command::new("secret-tool")
+        .arg("lookup")
+        .args(SECRET_ATTRIBUTES)
+        .output()
+        .context("Failed to execute secret-tool (is libsecret installed?)")?;
+
+    // secret-tool exits with 1 when nothing is stored
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(non_empty(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Stores the API key in the Secret Service via libsecret's `secret-tool`.
+pub fn store_secret_service(key: &str) -> Result<()> {
+    let mut child = Command::new("secret-tool")
+        .arg("store")
+        .arg("--label=Wallhaven API Key (hyprwallhaven)")
+        .args(SECRET_ATTRIBUTES)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to execute secret-tool (is libsecret installed?)")?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        stdin.write_all(key.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("secret-tool store failed");
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use crate::config::Config;
+use crate::hyprland;
+use crate::state::WallpaperEntry;
+use anyhow::{Context, Result};
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+
+/// What a preview session changed and has to put back.
+struct Original {
+    config: Config,
+    desktop: hyprland::ClearedDesktop,
+    /// Monitors the session shows wallpapers on, with each one's own wallpaper from before
+    wallpapers: Vec<(String, Option<WallpaperEntry>)>,
+}
+
+/// The running preview session, shared with the signal handler.
+static ACTIVE: Mutex<Option<Original>> = Mutex::new(None);
+
+/// Guard for a preview session (clearing the desktop as configured by
+/// `preview_workspace` and trying wallpapers). Whichever way the session ends
+/// the desktop is restored; the original wallpapers are restored unless the preview is kept.
+/// Dropping the guard (errors, early returns) counts as cancelling.
+pub struct PreviewSession {
+    _private: (),
+}
+
+impl PreviewSession {
+    pub fn begin(
+        config: &Config,
+        monitor: &hyprland::Monitor,
+        wallpapers: Vec<(String, Option<WallpaperEntry>)>,
+    ) -> Result<Self> {
+        let strategy = hyprland::PreviewWorkspace::parse(&config.preview_workspace)
+            .context("Invalid preview_workspace")?;
+        let desktop = hyprland::clear_desktop(&strategy, monitor)?;
+        *active() = Some(Original {
+            config: config.clone(),
+            desktop,
+            wallpapers,
+        });
+        Ok(PreviewSession { _private: () })
+    }
+
+    /// Keeps the previewed wallpaper and restores the desktop.
+    pub fn keep(self) -> Result<()> {
+        finish(false)
+    }
+
+    /// Restores the desktop and the original wallpapers.
+    pub fn cancel(self) -> Result<()> {
+        finish(true)
+    }
+}
+
+impl Drop for PreviewSession {
+    fn drop(&mut self) {
+        // No-op after keep/cancel, the session is already taken
+        if let Err(e) = finish(true) {
+            eprintln!("Warning: Failed to restore after preview: {}", e);
+        }
+    }
+}
+
+fn active() -> std::sync::MutexGuard<'static, Option<Original>> {
+    ACTIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn finish(restore_wallpaper: bool) -> Result<()> {
+    // Hold the lock while restoring so the signal handler can't exit halfway through
+    let mut active = active();
+    match active.take() {
+        Some(original) => original.restore(restore_wallpaper),
+        None => Ok(()),
+    }
+}
+
+impl Original {
+    fn restore(&self, restore_wallpaper: bool) -> Result<()> {
+        let desktop = hyprland::restore_desktop(&self.desktop);
+        if restore_wallpaper {
+            for (monitor, original) in &self.wallpapers {
+                if let Some(original) = original {
+                    crate::restore_own_wallpaper(original, &self.config, monitor)?;
+                }
+            }
+        }
+        desktop
+    }
+}
+
+/// Restores the running preview session on Ctrl-C, SIGTERM (e.g. a second
+/// invocation cancelling this one) and SIGHUP before exiting.
+pub fn install_signal_handler() {
+    let result = ctrlc::set_handler(|| {
+        // Taking the lock first keeps the main thread from starting anything new
+        let mut active = active();
+        close_menus();
+        if let Some(original) = active.take() {
+            if let Err(e) = original.restore(true) {
+                eprintln!("Warning: Failed to restore after preview: {}", e);
+            }
+        }
+        std::process::exit(130);
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: Failed to install signal handler: {}", e);
+    }
+}
+
+/// Closes any fuzzel menu we have open.
+fn close_menus() {
+    let _ = Command::new("pkill")
+        .arg("-TERM")
+        .arg("-P")
+        .arg(std::process::id().to_string())
+        .status();
+}
@@ -0,0 +1,144 @@
+use crate::hyprland::Monitor;
+use crate::jpeg;
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use std::path::{Path, PathBuf};
+
+/// Landscape aspect ratios Wallhaven can filter by, narrowest first.
+const WIDE_RATIOS: &[(&str, f64)] = &[
+    ("16x10", 16.0 / 10.0),
+    ("16x9", 16.0 / 9.0),
+    ("21x9", 21.0 / 9.0),
+    ("32x9", 32.0 / 9.0),
+    ("48x9", 48.0 / 9.0),
+];
+
+/// A monitor's place in the layout, in logical pixels relative to its top-left corner.
+#[derive(Debug, Clone)]
+struct Output {
+    name: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Resolution the piece is saved at (the monitor's, after its transform)
+    pixels: (u32, u32),
+}
+
+/// The combined layout of the monitors one wallpaper is spanned across.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    width: f64,
+    height: f64,
+    /// Highest monitor scale, so no piece has to be upscaled
+    scale: f64,
+    outputs: Vec<Output>,
+}
+
+impl Layout {
+    pub fn new(monitors: &[Monitor]) -> Result<Layout> {
+        if monitors.is_empty() {
+            anyhow::bail!("No monitors to span across");
+        }
+        let bounds: Vec<(f64, f64, f64, f64)> = monitors
+            .iter()
+            .map(|m| {
+                let (width, height) = m.get_logical_dimensions();
+                (m.x as f64, m.y as f64, width, height)
+            })
+            .collect();
+        let left = bounds.iter().map(|b| b.0).fold(f64::MAX, f64::min);
+        let top = bounds.iter().map(|b| b.1).fold(f64::MAX, f64::min);
+        let right = bounds.iter().map(|b| b.0 + b.2).fold(f64::MIN, f64::max);
+        let bottom = bounds.iter().map(|b| b.1 + b.3).fold(f64::MIN, f64::max);
+        let scale = monitors
+            .iter()
+            .map(|m| m.scale)
+            .filter(|s| *s > 0.0)
+            .fold(1.0, f64::max);
+
+        let outputs = monitors
+            .iter()
+            .zip(&bounds)
+            .map(|(m, &(x, y, width, height))| {
+                let (pixel_width, pixel_height) = m.get_visual_dimensions();
+                Output {
+                    name: m.name.clone(),
+                    x: x - left,
+                    y: y - top,
+                    width,
+                    height,
+                    pixels: (pixel_width.max(1) as u32, pixel_height.max(1) as u32),
+                }
+            })
+            .collect();
+
+        Ok(Layout {
+            width: right - left,
+            height: bottom - top,
+            scale,
+            outputs,
+        })
+    }
+
+    /// Resolution of the whole layout, the minimum size of a spanned image.
+    pub fn resolution(&self) -> (u32, u32) {
+        (
+            (self.width * self.scale).round().max(1.0) as u32,
+            (self.height * self.scale).round().max(1.0) as u32,
+        )
+    }
+
+    /// Wallhaven ratios about as wide as the layout or wider.
+    pub fn ratios(&self) -> String {
+        let aspect = self.width / self.height;
+        let ratios: Vec<&str> = WIDE_RATIOS
+            .iter()
+            .filter(|(_, ratio)| *ratio >= aspect * 0.9)
+            .map(|(name, _)| *name)
+            .collect();
+        if ratios.is_empty() {
+            "48x9".to_string()
+        } else {
+            ratios.join(",")
+        }
+    }
+}
+
+/// Where a monitor's piece of `image` is saved, next to the image.
+pub fn piece_path(image: &Path, monitor: &str) -> PathBuf {
+    let stem = image
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    image.with_file_name(format!("{}.span-{}.jpg", stem, monitor))
+}
+
+/// Scales `image` to cover the layout, cuts out each monitor's piece and saves
+/// it at the monitor's resolution. Returns the piece path for each monitor.
+pub fn split(image: &Path, layout: &Layout) -> Result<Vec<(String, PathBuf)>> {
+    let source =
+        image::open(image).with_context(|| format!("Failed to open {}", image.display()))?;
+    let (canvas_width, canvas_height) = layout.resolution();
+    let canvas = source.resize_to_fill(canvas_width, canvas_height, FilterType::CatmullRom);
+
+    let mut pieces = Vec::new();
+    for output in &layout.outputs {
+        let px = |v: f64| (v * layout.scale).round() as u32;
+        let x = px(output.x).min(canvas_width - 1);
+        let y = px(output.y).min(canvas_height - 1);
+        let width = px(output.width).clamp(1, canvas_width - x);
+        let height = px(output.height).clamp(1, canvas_height - y);
+        let (pixel_width, pixel_height) = output.pixels;
+        let piece = canvas.crop_imm(x, y, width, height).resize_exact(
+            pixel_width,
+            pixel_height,
+            FilterType::CatmullRom,
+        );
+
+        let path = piece_path(image, &output.name);
+        jpeg::save(&piece.to_rgb8(), &path)?;
+        pieces.push((output.name.clone(), path));
+    }
+    Ok(pieces)
+}
@@ -1,37 +1,300 @@
+use crate::wallhaven::Wallpaper;
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct State {
-    pub wallpapers: HashMap<String, String>, // monitor_name -> file_path
+    #[serde(default, deserialize_with = "deserialize_wallpapers")]
+    pub wallpapers: HashMap<String, WallpaperEntry>, // monitor_name -> wallpaper
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub history: HashMap<String, History>, // monitor_name -> history
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub workspaces: HashMap<String, WallpaperEntry>, // workspace name -> pinned wallpaper
 }
 
-pub fn load_state() -> Result<State> {
-    let config_dir = dirs::config_dir()
+impl State {
+    /// The most recently set wallpaper on any monitor.
+    pub fn newest(&self) -> Option<&WallpaperEntry> {
+        self.wallpapers.values().max_by_key(|entry| entry.set_at)
+    }
+}
+
+/// A wallpaper set on a monitor, with where it came from.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct WallpaperEntry {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wallhaven_id: Option<String>,
+    /// Wallhaven page or direct image URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// e.g. "3840x2160"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Wallhaven purity: sfw, sketchy or nsfw
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purity: Option<String>,
+    /// Unix timestamp of when the wallpaper was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set_at: Option<u64>,
+    /// Wallpaper mode (contain, cover, ...) it was applied with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+impl WallpaperEntry {
+    /// An entry for a local image without any metadata.
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            set_at: Some(now()),
+            ..Self::default()
+        }
+    }
+
+    /// An entry for an image downloaded from a direct URL.
+    pub fn from_url(path: &Path, url: &str) -> Self {
+        Self {
+            source_url: Some(url.to_string()),
+            ..Self::new(path)
+        }
+    }
+
+    pub fn from_wallhaven(path: &Path, wallpaper: &Wallpaper) -> Self {
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        Self {
+            wallhaven_id: Some(wallpaper.id.clone()),
+            source_url: non_empty(&wallpaper.short_url),
+            resolution: non_empty(&wallpaper.resolution),
+            tags: wallpaper.tags.iter().map(|t| t.name.clone()).collect(),
+            purity: non_empty(&wallpaper.purity),
+            ..Self::new(path)
+        }
+    }
+
+    /// Short name for menus: the Wallhaven ID or the file name.
+    pub fn display_name(&self) -> String {
+        self.wallhaven_id.clone().unwrap_or_else(|| {
+            Path::new(&self.path)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.path.clone())
+        })
+    }
+}
+
+/// Wallpapers previously set on a monitor, oldest first.
+/// `position` points at the current entry; entries after it can be redone.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct History {
+    pub entries: Vec<WallpaperEntry>,
+    pub position: usize,
+}
+
+impl History {
+    /// Records a newly set wallpaper, dropping the redo tail and the oldest entries beyond `limit`.
+    pub fn push(&mut self, entry: WallpaperEntry, limit: usize) {
+        if !self.entries.is_empty() {
+            self.entries.truncate(self.position + 1);
+        }
+        // Re-applying the current wallpaper only refreshes its timestamp
+        if self.entries.last().map(|e| &e.path) == Some(&entry.path) {
+            self.entries.pop();
+        }
+        self.entries.push(entry);
+        if self.entries.len() > limit.max(1) {
+            let excess = self.entries.len() - limit.max(1);
+            self.entries.drain(..excess);
+        }
+        self.position = self.entries.len() - 1;
+    }
+
+    /// Moves back one entry and returns it.
+    pub fn undo(&mut self) -> Option<&WallpaperEntry> {
+        if self.position == 0 || self.position >= self.entries.len() {
+            return None;
+        }
+        self.position -= 1;
+        self.entries.get(self.position)
+    }
+
+    /// Moves forward one entry and returns it.
+    pub fn redo(&mut self) -> Option<&WallpaperEntry> {
+        if self.position + 1 >= self.entries.len() {
+            return None;
+        }
+        self.position += 1;
+        self.entries.get(self.position)
+    }
+}
+
+/// Current Unix timestamp in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Older state files stored a plain path string per monitor.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Path(String),
+    Entry(WallpaperEntry),
+}
+
+fn deserialize_wallpapers<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, WallpaperEntry>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let stored = HashMap::<String, StoredEntry>::deserialize(deserializer)?;
+    Ok(stored
+        .into_iter()
+        .map(|(monitor, entry)| {
+            let entry = match entry {
+                // Unknown when it was set, so it doesn't count as newest
+                StoredEntry::Path(path) => WallpaperEntry {
+                    path,
+                    ..WallpaperEntry::default()
+                },
+                StoredEntry::Entry(entry) => entry,
+            };
+            (monitor, entry)
+        })
+        .collect())
+}
+
+fn state_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
         .context("Could not find config directory")?
-        .join("hypr");
-    let state_path = config_dir.join("hyprwallhaven_state.toml");
+        .join("hypr"))
+}
+
+fn state_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("hyprwallhaven_state.toml"))
+}
+
+fn backup_path(state_path: &Path) -> PathBuf {
+    state_path.with_extension("toml.bak")
+}
+
+/// An exclusive advisory lock on the state, released when dropped.
+/// Prevents concurrent load-modify-save cycles (e.g. a timer-driven rotate
+/// and an interactive menu) from losing each other's changes.
+pub struct StateLock {
+    _file: File,
+}
+
+pub fn lock_state() -> Result<StateLock> {
+    let dir = state_dir()?;
+    fs::create_dir_all(&dir)?;
+    let lock_path = dir.join("hyprwallhaven_state.lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+    Ok(StateLock { _file: file })
+}
+
+fn parse_state(path: &Path) -> Result<State> {
+    let content = fs::read_to_string(path)?;
+    Ok(toml::from_str(&content)?)
+}
+
+/// Loads the state. Writes are atomic, so this does not need the lock.
+/// A corrupt state file is recovered from the backup kept by the last save.
+pub fn load_state() -> Result<State> {
+    let state_path = state_path()?;
 
     if !state_path.exists() {
         return Ok(State::default());
     }
 
-    let content = fs::read_to_string(&state_path)?;
-    // If parsing fails, return default state instead of crashing
-    let state: State = toml::from_str(&content).unwrap_or_default();
-    Ok(state)
+    match parse_state(&state_path) {
+        Ok(state) => Ok(state),
+        Err(e) => {
+            let backup = backup_path(&state_path);
+            let state = parse_state(&backup).with_context(|| {
+                format!(
+                    "Failed to load {} ({}) and no usable backup at {}",
+                    state_path.display(),
+                    e,
+                    backup.display()
+                )
+            })?;
+            eprintln!(
+                "Warning: {} is unreadable ({}), recovered from {}",
+                state_path.display(),
+                e,
+                backup.display()
+            );
+            Ok(state)
+        }
+    }
 }
 
+/// Writes the state atomically: the new content goes to a temp file which then
+/// replaces the state file, after the previous version is copied to `.bak`.
+/// Callers should hold the lock; prefer `update_state`.
 pub fn save_state(state: &State) -> Result<()> {
-    let config_dir = dirs::config_dir()
-        .context("Could not find config directory")?
-        .join("hypr");
-    fs::create_dir_all(&config_dir)?; 
-    let state_path = config_dir.join("hyprwallhaven_state.toml");
+    let state_path = state_path()?;
+    fs::create_dir_all(state_dir()?)?;
 
     let toml_string = toml::to_string_pretty(state)?;
-    fs::write(&state_path, toml_string)?;
+    let tmp_path = state_path.with_extension(format!("toml.tmp.{}", std::process::id()));
+    {
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        file.write_all(toml_string.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    // Only back up a state that is itself readable, so a bad file never replaces a good backup
+    if parse_state(&state_path).is_ok() {
+        fs::copy(&state_path, backup_path(&state_path))?;
+    }
+
+    fs::rename(&tmp_path, &state_path)
+        .with_context(|| format!("Failed to replace {}", state_path.display()))?;
     Ok(())
 }
+
+/// Loads the state, applies `f` and saves the result while holding the lock.
+/// A state that can't be loaded even from the backup is moved aside to
+/// `.corrupt.<timestamp>` and replaced by a fresh one.
+pub fn update_state<T>(f: impl FnOnce(&mut State) -> T) -> Result<T> {
+    let _lock = lock_state()?;
+    let mut state = match load_state() {
+        Ok(state) => state,
+        Err(e) => {
+            let state_path = state_path()?;
+            let corrupt = state_path.with_extension(format!("toml.corrupt.{}", now()));
+            fs::rename(&state_path, &corrupt)
+                .with_context(|| format!("Failed to move aside {}", state_path.display()))?;
+            eprintln!(
+                "Warning: {:#}. Moved it to {} and starting with a fresh state",
+                e,
+                corrupt.display()
+            );
+            State::default()
+        }
+    };
+    let result = f(&mut state);
+    save_state(&state)?;
+    Ok(result)
+}
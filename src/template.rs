@@ -0,0 +1,280 @@
+use crate::state::WallpaperEntry;
+
+/// Placeholder values, looked up by name (`{path}`) or, for the two original
+/// `wallpaper_cmd` placeholders, by `%f` and `%m`. Values can be unset, e.g.
+/// the Wallhaven ID of a local image.
+pub struct Values {
+    values: Vec<(&'static str, Option<String>)>,
+}
+
+impl Values {
+    /// The wallpaper's values: path, monitor, width, height, id, mode, purity, url and tags.
+    pub fn new(entry: &WallpaperEntry, monitor: &str, mode: &str) -> Self {
+        let (width, height) = entry
+            .resolution
+            .as_deref()
+            .and_then(crate::config::parse_resolution)
+            .map(|(w, h)| (w.to_string(), h.to_string()))
+            .unzip();
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        Values {
+            values: vec![
+                ("path", Some(entry.path.clone())),
+                ("monitor", Some(monitor.to_string())),
+                ("width", width),
+                ("height", height),
+                ("id", entry.wallhaven_id.clone()),
+                ("mode", non_empty(mode)),
+                ("purity", entry.purity.clone()),
+                ("url", entry.source_url.clone()),
+                ("tags", non_empty(&entry.tags.join(", "))),
+            ],
+        }
+    }
+
+    /// Adds (or replaces) a value.
+    pub fn with(mut self, name: &'static str, value: Option<String>) -> Self {
+        self.values.retain(|(n, _)| *n != name);
+        self.values.push((name, value));
+        self
+    }
+
+    /// Every value as an environment variable for `render_shell`'s script.
+    /// Unset values are empty.
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (env_name(name), value.clone().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Matches a placeholder at the start of `s`: its length, name and value.
+    fn lookup(&self, s: &str) -> Option<(usize, &'static str, Option<&str>)> {
+        let name = match s.get(..2) {
+            Some("%f") => Some(("path", 2)),
+            Some("%m") => Some(("monitor", 2)),
+            _ => s
+                .strip_prefix('{')
+                .and_then(|rest| rest.split_once('}'))
+                .map(|(name, _)| (name, name.len() + 2)),
+        };
+        let (name, len) = name?;
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(n, value)| (len, *n, value.as_deref()))
+    }
+}
+
+/// Fills in placeholders line by line for a config file. Lines with a
+/// placeholder that has no value are left out.
+pub fn render_lines(template: &str, values: &Values) -> String {
+    let mut out = String::new();
+    'lines: for line in template.split_inclusive('\n') {
+        let mut rendered = String::new();
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            match values.lookup(rest) {
+                Some((_, _, None)) => continue 'lines,
+                Some((len, _, Some(value))) => {
+                    rendered.push_str(value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    rendered.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        out.push_str(&rendered);
+    }
+    out
+}
+
+/// Fills in placeholders verbatim, for commands run without a shell.
+pub fn render_arg(template: &str, values: &Values) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if let Some((len, _, value)) = values.lookup(rest) {
+            out.push_str(value.unwrap_or_default());
+            rest = &rest[len..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// Name of the environment variable a value is passed in, e.g. `HW_PATH`.
+fn env_name(name: &str) -> String {
+    format!("HW_{}", name.to_uppercase())
+}
+
+/// Where in a shell script a placeholder appears.
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Bare,
+    Single,
+    Double,
+    /// `$(...)` or `(...)`, quoted like bare text
+    Subshell,
+    /// `` `...` ``, quoted like bare text
+    Backtick,
+}
+
+/// Turns placeholders into references to the environment variables from
+/// `Values::env`, for a command run through `sh`. The values never become part
+/// of the script, so the shell can't run them as commands whatever they
+/// contain. Each reference is quoted for where it appears, so a value stays
+/// one word.
+pub fn render_shell(template: &str, values: &Values) -> String {
+    let mut out = String::new();
+    let mut stack = vec![Context::Bare];
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        let context = *stack.last().unwrap_or(&Context::Bare);
+        if let Some((len, name, _)) = values.lookup(rest) {
+            let var = env_name(name);
+            out.push_str(&match context {
+                Context::Double => format!("${{{}}}", var),
+                Context::Single => format!("'\"${{{}}}\"'", var),
+                _ => format!("\"${{{}}}\"", var),
+            });
+            rest = &rest[len..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+        match (context, c) {
+            (Context::Single, '\'')
+            | (Context::Double, '"')
+            | (Context::Backtick, '`')
+            | (Context::Subshell, ')') => {
+                stack.pop();
+            }
+            (Context::Single, _) => {}
+            // A backslash keeps the next character literal, except inside '...'
+            (_, '\\') => {
+                if let Some(next) = rest.chars().next() {
+                    out.push(next);
+                    rest = &rest[next.len_utf8()..];
+                }
+            }
+            (_, '$') if rest.starts_with('(') => {
+                out.push('(');
+                rest = &rest[1..];
+                stack.push(Context::Subshell);
+            }
+            (Context::Double, '`') => stack.push(Context::Backtick),
+            (Context::Double, _) => {}
+            (_, '\'') => stack.push(Context::Single),
+            (_, '"') => stack.push(Context::Double),
+            (_, '`') => stack.push(Context::Backtick),
+            (_, '(') => stack.push(Context::Subshell),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    const NASTY: &str = "/tmp/a b/it's \"q\"; echo pwned $(echo pwned) `echo pwned` \\ {x}.jpg";
+
+    /// Renders `template` for `path` and returns what `sh` prints.
+    fn run(template: &str, path: &str) -> String {
+        let values = Values::new(&WallpaperEntry::new(Path::new(path)), "DP-1", "cover");
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(render_shell(template, &values))
+            .envs(values.env())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn bare() {
+        assert_eq!(run("printf '%s|' {path}", NASTY), format!("{}|", NASTY));
+    }
+
+    #[test]
+    fn double_quotes() {
+        assert_eq!(
+            run("printf '%s|' \"on {monitor}: {path}\"", NASTY),
+            format!("on DP-1: {}|", NASTY)
+        );
+    }
+
+    #[test]
+    fn single_quotes() {
+        assert_eq!(
+            run("printf '%s|' 'file {path}'", NASTY),
+            format!("file {}|", NASTY)
+        );
+    }
+
+    #[test]
+    fn command_substitution() {
+        assert_eq!(
+            run("printf '%s|' \"Set $(printf '%s' {path})\"", NASTY),
+            format!("Set {}|", NASTY)
+        );
+        assert_eq!(
+            run("printf '%s|' $(printf '%s' \"{path}\")", "/tmp/a.jpg"),
+            "/tmp/a.jpg|"
+        );
+    }
+
+    #[test]
+    fn backticks() {
+        assert_eq!(
+            run("x=`printf '%s' {path}`; printf '%s|' \"$x\"", NASTY),
+            format!("{}|", NASTY)
+        );
+    }
+
+    #[test]
+    fn legacy_placeholders() {
+        assert_eq!(run("printf '%s|' %m %f", NASTY), format!("DP-1|{}|", NASTY));
+    }
+
+    #[test]
+    fn escaped_placeholder() {
+        assert_eq!(
+            run("printf '%s|' \\{path\\} ${HOME+set}", NASTY),
+            "{path}|set|"
+        );
+    }
+
+    #[test]
+    fn unset_values() {
+        assert_eq!(
+            run("printf '%s|' \"{id}\" {path}", "/tmp/a.jpg"),
+            "|/tmp/a.jpg|"
+        );
+    }
+
+    #[test]
+    fn render_arg_is_verbatim() {
+        let values = Values::new(&WallpaperEntry::new(Path::new(NASTY)), "DP-1", "cover");
+        assert_eq!(render_arg("-i{path}", &values), format!("-i{}", NASTY));
+    }
+
+    #[test]
+    fn render_lines_drops_unset() {
+        let values =
+            Values::new(&WallpaperEntry::new(Path::new("/a.jpg")), "DP-1", "").with("blur", None);
+        assert_eq!(
+            render_lines("# {url}\npath = {path}\nblur = {blur}\n}\n", &values),
+            "path = /a.jpg\n}\n"
+        );
+    }
+}
@@ -8,6 +8,7 @@ pub enum MenuAction {
     SetId,
     Settings,
     Collections,
+    History,
     OpenCurrent,
     Custom(String),
     None,
@@ -23,6 +24,7 @@ pub enum NavAction {
     SettingsPurity,
     SettingsSorting,
     Done,
+    PinToWorkspace,
     Cancel,
     None,
 }
@@ -38,11 +40,13 @@ pub enum SettingsAction {
 }
 
 pub fn show_fuzzel_menu(show_current: bool) -> Result<MenuAction> {
-    let mut options = String::from("🎲 Rotate\n🔍 Search\n📚 Collections\n🆔 Set ID/URL\n⚙️ Settings\n");
-    let mut lines = 4;
+    let mut options = String::from(
+        "🎲 Rotate\n🔍 Search\n📚 Collections\n🕘 History\n🆔 Set ID/URL\n⚙️ Settings\n",
+    );
+    let mut lines = 5;
     if show_current {
         options.push_str("👁️ Show Current Wallpaper\n");
-        lines = 5;
+        lines = 6;
     }
 
     let mut child = Command::new("fuzzel")
@@ -68,6 +72,7 @@ pub fn show_fuzzel_menu(show_current: bool) -> Result<MenuAction> {
         s if s.contains("Rotate") => Ok(MenuAction::Rotate),
         s if s.contains("Search") => Ok(MenuAction::SearchApi),
         s if s.contains("Collections") => Ok(MenuAction::Collections),
+        s if s.contains("History") => Ok(MenuAction::History),
         s if s.contains("Set ID") => Ok(MenuAction::SetId),
         s if s.contains("Settings") => Ok(MenuAction::Settings),
         s if s.contains("Show Current Wallpaper") => Ok(MenuAction::OpenCurrent),
@@ -83,7 +88,7 @@ pub fn show_settings_menu(
     wallpaper_mode: &str,
 ) -> Result<SettingsAction> {
     let mut cat_list = Vec::new();
-    if categories.chars().nth(0).unwrap_or('0') == '1' {
+    if categories.chars().next().unwrap_or('0') == '1' {
         cat_list.push("General");
     }
     if categories.chars().nth(1).unwrap_or('0') == '1' {
@@ -99,7 +104,7 @@ pub fn show_settings_menu(
     };
 
     let mut purity_list = Vec::new();
-    if purity.chars().nth(0).unwrap_or('0') == '1' {
+    if purity.chars().next().unwrap_or('0') == '1' {
         purity_list.push("SFW");
     }
     if purity.chars().nth(1).unwrap_or('0') == '1' {
@@ -151,14 +156,20 @@ pub fn show_settings_menu(
     }
 }
 
-pub fn show_wallpaper_mode_menu(_current: &str) -> Result<Option<String>> {
-    let options = "contain\ncover\nfill\ntile\n🔙 Back\n";
+/// Lets the user pick one of the wallpaper modes the backend supports.
+pub fn show_wallpaper_mode_menu(_current: &str, modes: &[&str]) -> Result<Option<String>> {
+    let mut options = String::new();
+    for mode in modes {
+        options.push_str(mode);
+        options.push('\n');
+    }
+    options.push_str("🔙 Back\n");
 
     let mut child = Command::new("fuzzel")
         .arg("--dmenu")
         .arg("-p")
         .arg("Wallpaper Mode: ")
-        .arg("--lines=5")
+        .arg(format!("--lines={}", modes.len() + 1))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()?;
@@ -204,7 +215,7 @@ pub fn get_password_input(prompt: &str) -> Result<String> {
 }
 
 pub fn show_categories_menu(current: &str) -> Result<Option<String>> {
-    let gen = if current.chars().nth(0).unwrap_or('0') == '1' {
+    let gen = if current.chars().next().unwrap_or('0') == '1' {
         "ON"
     } else {
         "OFF"
@@ -265,7 +276,7 @@ pub fn show_categories_menu(current: &str) -> Result<Option<String>> {
 }
 
 pub fn show_purity_menu(current: &str) -> Result<Option<String>> {
-    let sfw = if current.chars().nth(0).unwrap_or('0') == '1' {
+    let sfw = if current.chars().next().unwrap_or('0') == '1' {
         "ON"
     } else {
         "OFF"
@@ -372,7 +383,7 @@ pub fn show_search_nav_menu(
     sorting: &str,
 ) -> Result<NavAction> {
     let mut cat_list = Vec::new();
-    if categories.chars().nth(0).unwrap_or('0') == '1' {
+    if categories.chars().next().unwrap_or('0') == '1' {
         cat_list.push("General");
     }
     if categories.chars().nth(1).unwrap_or('0') == '1' {
@@ -388,7 +399,7 @@ pub fn show_search_nav_menu(
     };
 
     let mut purity_list = Vec::new();
-    if purity.chars().nth(0).unwrap_or('0') == '1' {
+    if purity.chars().next().unwrap_or('0') == '1' {
         purity_list.push("SFW");
     }
     if purity.chars().nth(1).unwrap_or('0') == '1' {
@@ -403,7 +414,9 @@ pub fn show_search_nav_menu(
         purity_list.join(", ")
     };
 
-    let mut options = String::from("➡️ Next\n⬅️ Prev\n✅ Done\n🎲 Random\n🌐 Open in Browser\n");
+    let mut options = String::from(
+        "➡️ Next\n⬅️ Prev\n✅ Done\n📌 Pin to this workspace\n🎲 Random\n🌐 Open in Browser\n",
+    );
     options.push_str(&format!("📂 Category [{}]\n", cat_str));
     options.push_str(&format!("🔞 Purity [{}]\n", purity_str));
     options.push_str(&format!("📶 Sorting [{}]\n", sorting));
@@ -442,6 +455,7 @@ pub fn show_search_nav_menu(
         s if s.contains("Purity") => Ok(NavAction::SettingsPurity),
         s if s.contains("Sorting") => Ok(NavAction::SettingsSorting),
         s if s.contains("Done") => Ok(NavAction::Done),
+        s if s.contains("Pin to this workspace") => Ok(NavAction::PinToWorkspace),
         _ => Ok(NavAction::None),
     }
 }
@@ -481,6 +495,23 @@ pub fn show_selection_menu(prompt: &str, items: &[String]) -> Result<Option<Stri
     Ok(Some(selection))
 }
 
+/// Formats how long ago a Unix timestamp was, e.g. "5m ago".
+pub fn format_age(timestamp: u64, now: u64) -> String {
+    let secs = now.saturating_sub(timestamp);
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Shows recent wallpapers and returns the index of the selected one.
+pub fn show_history_menu(items: &[String]) -> Result<Option<usize>> {
+    let selection = show_selection_menu("History: ", items)?;
+    Ok(selection.and_then(|s| items.iter().position(|item| *item == s)))
+}
+
 pub fn show_preview_menu() -> Result<NavAction> {
     let options = "✅ Done\n❌ Cancel\n🌐 Open in Browser\n";
 
@@ -4,13 +4,90 @@ use reqwest::blocking::Client;
 use serde::Deserialize;
 use std::fs;
 use std::io::copy;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Wallpaper {
     pub id: String,
     pub short_url: String,
     pub path: String, // API returns 'path' as the full image url usually
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub purity: String,
+    #[serde(default)]
+    pub resolution: String,
+    #[serde(default)]
+    pub created_at: String, // "YYYY-MM-DD HH:MM:SS"
+    #[serde(default)]
+    pub tags: Vec<Tag>, // Only returned by the wallpaper info endpoint
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+}
+
+/// Placeholders supported in `filename_template`.
+pub const FILENAME_PLACEHOLDERS: &[&str] = &[
+    "id",
+    "ext",
+    "category",
+    "purity",
+    "resolution",
+    "first_tag",
+    "date",
+];
+
+/// Makes a value safe to use as a single path component.
+fn sanitize_component(value: &str) -> String {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\\' || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    match cleaned.as_str() {
+        "" | "." | ".." => "unknown".to_string(),
+        _ => cleaned,
+    }
+}
+
+/// Renders `filename_template` for a wallpaper, e.g. "{category}/wallhaven-{id}.{ext}".
+/// The result is relative to `save_dir` and may contain subdirectories.
+pub fn render_filename(template: &str, wallpaper: &Wallpaper) -> String {
+    let ext = wallpaper.path.rsplit('.').next().unwrap_or("jpg");
+    let first_tag = wallpaper
+        .tags
+        .first()
+        .map(|t| t.name.as_str())
+        .unwrap_or("");
+    let date = wallpaper.created_at.get(..10).unwrap_or("");
+
+    let mut filename = template.to_string();
+    for (key, value) in [
+        ("id", wallpaper.id.as_str()),
+        ("ext", ext),
+        ("category", wallpaper.category.as_str()),
+        ("purity", wallpaper.purity.as_str()),
+        ("resolution", wallpaper.resolution.as_str()),
+        ("first_tag", first_tag),
+        ("date", date),
+    ] {
+        filename = filename.replace(&format!("{{{}}}", key), &sanitize_component(value));
+    }
+    filename
+}
+
+/// Where a Wallhaven wallpaper is stored in the library.
+pub fn library_path(config: &Config, wallpaper: &Wallpaper) -> PathBuf {
+    let relative = render_filename(&config.filename_template, wallpaper);
+    crate::config::expand_path(&config.save_dir).join(relative.trim_start_matches('/'))
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -65,7 +142,11 @@ pub fn search_wallpapers(
         url.push_str(&format!("&q={}", q));
     }
 
-    if let Some(key) = &config.api_key {
+    if let Some(atleast) = &config.atleast {
+        url.push_str(&format!("&atleast={}", atleast));
+    }
+
+    if let Some(key) = config.api_key()? {
         url.push_str(&format!("&apikey={}", key));
     }
 
@@ -82,7 +163,7 @@ pub fn search_wallpapers(
 pub fn get_wallpaper_info(id: &str, config: &Config) -> Result<Wallpaper> {
     let client = Client::new();
     let mut url = format!("https://wallhaven.cc/api/v1/w/{}", id);
-    if let Some(key) = &config.api_key {
+    if let Some(key) = config.api_key()? {
         url.push_str(&format!("?apikey={}", key));
     }
 
@@ -115,7 +196,7 @@ pub fn get_my_collections(config: &Config) -> Result<Vec<Collection>> {
     let client = Client::new();
     let mut url = "https://wallhaven.cc/api/v1/collections".to_string();
 
-    if let Some(key) = &config.api_key {
+    if let Some(key) = config.api_key()? {
         url.push_str(&format!("?apikey={}", key));
     } else {
         anyhow::bail!("API Key required to fetch collections");
@@ -139,7 +220,7 @@ pub fn get_collection_wallpapers(
         username, collection_id, page
     );
 
-    if let Some(key) = &config.api_key {
+    if let Some(key) = config.api_key()? {
         url.push_str(&format!("&apikey={}", key));
     } else {
         // Collections might be public, but let's stick to consistent API usage if key is present
@@ -161,7 +242,7 @@ pub fn get_username(config: &Config) -> Result<String> {
     let client = Client::new();
     let mut url = "https://wallhaven.cc/api/v1/settings".to_string();
 
-    if let Some(key) = &config.api_key {
+    if let Some(key) = config.api_key()? {
         url.push_str(&format!("?apikey={}", key));
     } else {
         anyhow::bail!("API Key required to fetch username");